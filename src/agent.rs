@@ -1,7 +1,8 @@
-use std::{f32::consts::{TAU}};
+use std::{f32::consts::{TAU, FRAC_PI_2}};
 use rand::{Rng};
 use ggez::{GameResult};
 use bytemuck::{Pod, Zeroable};
+use crate::{Heading};
 
 
 #[repr(C)]
@@ -66,4 +67,49 @@ impl Agent {
 
         return Ok(self);
     }
+
+    pub fn with_position(mut self, position_x: f32, position_y: f32) -> GameResult<Agent> {
+        self.position_x = position_x;
+        self.position_y = position_y;
+
+        return Ok(self);
+    }
+
+    // `aspect` is the map width over height, circles are squashed along y so they
+    // stay round on non-square maps.
+    pub fn random_position_in_disc<R: Rng + ?Sized>(mut self, rng: &mut R, center: [f32; 2], radius: f32, aspect: f32) -> GameResult<Agent> {
+        let angle = rng.gen::<f32>() * TAU;
+        let distance = rng.gen::<f32>().sqrt() * radius;
+
+        self.position_x = center[0] + angle.cos() * distance;
+        self.position_y = center[1] + angle.sin() * distance * aspect;
+
+        return Ok(self);
+    }
+
+    pub fn random_position_on_ring<R: Rng + ?Sized>(mut self, rng: &mut R, radius: f32, aspect: f32) -> GameResult<Agent> {
+        let angle = rng.gen::<f32>() * TAU;
+
+        self.position_x = angle.cos() * radius;
+        self.position_y = angle.sin() * radius * aspect;
+
+        return Ok(self);
+    }
+
+    pub fn with_heading<R: Rng + ?Sized>(self, rng: &mut R, heading: Heading, aspect: f32) -> GameResult<Agent> {
+        let outward = (self.position_y / aspect).atan2(self.position_x);
+
+        return match heading {
+            Heading::Random => self.random_angle(rng),
+            Heading::Outward => self.with_angle(outward),
+            Heading::Inward => self.with_angle(outward + TAU / 2.0),
+            Heading::Tangent => self.with_angle(outward + FRAC_PI_2),
+        };
+    }
+
+    pub fn with_angle(mut self, angle: f32) -> GameResult<Agent> {
+        self.angle = angle;
+
+        return Ok(self);
+    }
 }
@@ -0,0 +1,112 @@
+use ggez::{GameResult};
+use crate::{BrushTool};
+
+
+// Uniform for `brush.wgsl`. Positions are in map space, [-1, 1] on both axes,
+// and `radius` is in units of the map width like the sensor distances.
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Brush {
+    pub position: [f32; 2],
+    pub radius: f32,
+    pub strength: f32,
+    pub tool: u32,
+    pub species: u32,
+    // Agents `spawn_start..spawn_start + spawn_count`, wrapping around the agent
+    // buffer, are the ones moved by `BrushTool::Spawn`.
+    pub spawn_start: u32,
+    pub spawn_count: u32,
+    pub seed: u32,
+    pub _padding: [u32; 3],
+}
+
+impl Brush {
+    pub fn new(tool: BrushTool, position: [f32; 2], radius: f32, strength: f32, species: u32) -> GameResult<Brush> {
+        let brush = Brush {
+            position,
+            radius,
+            strength,
+            tool: tool.index(),
+            species,
+            ..Default::default()
+        };
+
+        return Ok(brush);
+    }
+
+    pub fn with_spawn(mut self, spawn_start: u32, spawn_count: u32, seed: u32) -> GameResult<Brush> {
+        self.spawn_start = spawn_start;
+        self.spawn_count = spawn_count;
+        self.seed = seed;
+
+        return Ok(self);
+    }
+}
+
+// Window pixels to map space. The top of the window is the top of the map,
+// which is the last row of trail cells, see `get_cell_index`.
+pub fn window_to_map(position: [f32; 2], window_size: [f32; 2]) -> [f32; 2] {
+    return [
+        position[0] / window_size[0] * 2.0 - 1.0,
+        1.0 - position[1] / window_size[1] * 2.0,
+    ];
+}
+
+// Offset from the brush centre measured in units of the map width.
+// Must match `brush_offset` in `brush.wgsl`.
+pub fn brush_offset(brush: &Brush, position: [f32; 2], aspect: f32) -> [f32; 2] {
+    return [position[0] - brush.position[0], (position[1] - brush.position[1]) / aspect];
+}
+
+// Must match `brush_falloff` in `brush.wgsl`.
+pub fn brush_falloff(distance: f32, radius: f32) -> f32 {
+    if distance >= radius { return 0.0; }
+
+    let t = 1.0 - distance / radius;
+
+    return t * t;
+}
+
+// Brush tool state edited in the panel and turned into one `Brush` per frame
+// while the mouse button is held.
+#[derive(Clone, Copy, Debug)]
+pub struct BrushSettings {
+    pub tool: BrushTool,
+    pub radius: f32,
+    pub strength: f32,
+    pub species: u32,
+    // Agents moved per frame by `BrushTool::Spawn`.
+    pub spawn_count: u32,
+    // Next agent to recycle, so consecutive spawns take different agents.
+    pub spawn_cursor: u32,
+    pub strokes: u32,
+}
+
+impl BrushSettings {
+    pub fn new() -> GameResult<BrushSettings> {
+        let brush_settings = BrushSettings {
+            tool: BrushTool::Paint,
+            radius: 0.05,
+            strength: 0.5,
+            species: 0,
+            spawn_count: 500,
+            spawn_cursor: 0,
+            strokes: 0,
+        };
+
+        return Ok(brush_settings);
+    }
+
+    pub fn stroke(&mut self, position: [f32; 2], agent_count: u32) -> GameResult<Brush> {
+        let spawn_count = self.spawn_count.min(agent_count);
+        let brush = Brush::new(self.tool, position, self.radius, self.strength, self.species)?
+            .with_spawn(self.spawn_cursor, spawn_count, self.strokes)?;
+
+        if self.tool == BrushTool::Spawn && agent_count > 0 {
+            self.spawn_cursor = ((self.spawn_cursor as u64 + spawn_count as u64) % agent_count as u64) as u32;
+        }
+        self.strokes = self.strokes.wrapping_add(1);
+
+        return Ok(brush);
+    }
+}
@@ -0,0 +1,112 @@
+use ggez::{GameResult};
+
+
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 64.0;
+
+// Uniform for `render_map.wgsl`. `offset` is the map position at the centre of
+// the window and `zoom` is how many times larger than the whole map fitting the
+// window it is drawn. The tiled view draws the map 3x3 times so the wrap at the
+// edges shows. `viewport` shrinks one axis to keep the map's aspect ratio, see `fit`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Camera {
+    pub offset: [f32; 2],
+    pub zoom: f32,
+    pub tiled: u32,
+    pub viewport: [f32; 2],
+    pub _padding: [f32; 2],
+}
+
+impl Camera {
+    pub fn new() -> GameResult<Camera> {
+        let camera = Camera { offset: [0.0, 0.0], zoom: 1.0, tiled: 0, viewport: [1.0, 1.0], _padding: [0.0; 2] };
+
+        return Ok(camera);
+    }
+
+    pub fn is_tiled(&self) -> bool {
+        return self.tiled != 0;
+    }
+
+    // How many copies of the map are drawn.
+    pub fn tile_count(&self) -> u32 {
+        return if self.is_tiled() { 9 } else { 1 };
+    }
+
+    // Stretches the map over the window, or with `keep_aspect` fits it inside
+    // the window at the map's own aspect ratio.
+    pub fn fit(&mut self, window_size: [f32; 2], map_size: [f32; 2], keep_aspect: bool) {
+        let window_aspect = window_size[0] / window_size[1];
+        let map_aspect = map_size[0] / map_size[1];
+
+        self.viewport = if !keep_aspect || !window_aspect.is_finite() || !map_aspect.is_finite() {
+            [1.0, 1.0]
+        } else if window_aspect > map_aspect {
+            [map_aspect / window_aspect, 1.0]
+        } else {
+            [1.0, window_aspect / map_aspect]
+        };
+    }
+
+    // Clip space units per map unit on each axis, the tiled view fits three maps
+    // per axis. Must match `view_scale` in `render_map.wgsl`.
+    pub fn scale(&self) -> [f32; 2] {
+        let zoom = if self.is_tiled() { self.zoom / 3.0 } else { self.zoom };
+
+        return [self.viewport[0] * zoom, self.viewport[1] * zoom];
+    }
+
+    pub fn map_to_view(&self, position: [f32; 2]) -> [f32; 2] {
+        let scale = self.scale();
+
+        return [(position[0] - self.offset[0]) * scale[0], (position[1] - self.offset[1]) * scale[1]];
+    }
+
+    // Inverse of `map_to_view`. In the tiled view every copy of the map leads
+    // back into [-1, 1).
+    pub fn view_to_map(&self, position: [f32; 2]) -> [f32; 2] {
+        let scale = self.scale();
+        let map_position = [position[0] / scale[0] + self.offset[0], position[1] / scale[1] + self.offset[1]];
+
+        if !self.is_tiled() { return map_position; }
+
+        return [wrap_map(map_position[0]), wrap_map(map_position[1])];
+    }
+
+    // Moves the camera by a distance in clip space, so the map follows the mouse.
+    pub fn pan(&mut self, delta: [f32; 2]) {
+        let scale = self.scale();
+
+        self.offset[0] -= delta[0] / scale[0];
+        self.offset[1] -= delta[1] / scale[1];
+
+        // Keeps the camera over the middle tile, which looks the same as any other.
+        if self.is_tiled() {
+            self.offset = [wrap_map(self.offset[0]), wrap_map(self.offset[1])];
+        }
+    }
+
+    // Zooms by `factor` keeping the map position under `position` (clip space) in place.
+    pub fn zoom_at(&mut self, position: [f32; 2], factor: f32) {
+        let scale = self.scale();
+        let anchor = [position[0] / scale[0] + self.offset[0], position[1] / scale[1] + self.offset[1]];
+
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let scale = self.scale();
+        self.offset = [anchor[0] - position[0] / scale[0], anchor[1] - position[1] / scale[1]];
+
+        if self.is_tiled() {
+            self.offset = [wrap_map(self.offset[0]), wrap_map(self.offset[1])];
+        }
+    }
+
+    pub fn toggle_tiled(&mut self) {
+        self.tiled = if self.is_tiled() { 0 } else { 1 };
+    }
+}
+
+fn wrap_map(value: f32) -> f32 {
+    return (value + 1.0).rem_euclid(2.0) - 1.0;
+}
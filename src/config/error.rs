@@ -0,0 +1,99 @@
+use std::fmt;
+use ggez::{GameError};
+
+
+// A problem in one config file. `file` is relative to the config root, e.g.
+// `species/0.ron`, and `position` is the 1 based line and column when known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    pub file: String,
+    pub position: Option<(usize, usize)>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(message: &str) -> ConfigError {
+        return ConfigError { file: String::new(), position: None, field: None, message: message.to_string() };
+    }
+
+    pub fn field(field: &str, message: &str) -> ConfigError {
+        return ConfigError { field: Some(field.to_string()), ..ConfigError::new(message) };
+    }
+
+    // Attaches the file name and, for field errors, the line the field is set on.
+    pub fn in_file(self, file: &str, contents: &str) -> ConfigError {
+        let position = self.position.or_else(|| self.field.as_deref().and_then(|field| find_field(contents, field)));
+
+        return ConfigError { file: file.to_string(), position, ..self };
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+
+        if let Some((line, column)) = self.position { write!(f, ":{}:{}", line, column)?; }
+        if let Some(field) = &self.field { write!(f, ": {}", field)?; }
+
+        return write!(f, ": {}", self.message);
+    }
+}
+
+impl From<ConfigError> for GameError {
+    fn from(error: ConfigError) -> GameError {
+        return GameError::ConfigError(error.to_string());
+    }
+}
+
+// One error per line, for reporting a whole validation pass at once.
+pub fn config_errors_to_game_error(errors: &Vec<ConfigError>) -> GameError {
+    let lines: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+
+    return GameError::ConfigError(lines.join("\n"));
+}
+
+// Line and column of `field:` in a RON file, skipping comments. A dotted field
+// like `bloom.radius` is looked for after the line its parent is set on.
+fn find_field(contents: &str, field: &str) -> Option<(usize, usize)> {
+    let mut position = None;
+    let mut start = 0;
+
+    for name in field.split('.') {
+        let (index, column) = find_field_from(contents, name, start)?;
+
+        position = Some((index + 1, column));
+        start = index + 1;
+    }
+
+    return position;
+}
+
+fn find_field_from(contents: &str, field: &str, start: usize) -> Option<(usize, usize)> {
+    for (index, line) in contents.lines().enumerate().skip(start) {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("//") { continue; }
+
+        if let Some(rest) = trimmed.strip_prefix(field) {
+            if rest.trim_start().starts_with(':') {
+                return Some((index, line.len() - trimmed.len() + 1));
+            }
+        }
+    }
+
+    return None;
+}
+
+// Reports `field` unless `value` is a finite number within `min..=max`.
+pub fn check_range(errors: &mut Vec<ConfigError>, field: &str, value: f32, min: f32, max: f32) {
+    if value.is_finite() && value >= min && value <= max { return; }
+
+    let message = if max == f32::INFINITY {
+        format!("must be at least {}, got {}", min, value)
+    } else {
+        format!("must be between {} and {}, got {}", min, max, value)
+    };
+
+    errors.push(ConfigError::field(field, &message));
+}
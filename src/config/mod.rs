@@ -1,27 +1,295 @@
-use std::{fs::File};
+use std::{fs, env, ffi::OsString, path::{Path, PathBuf}, sync::RwLock};
 use serde::{de::DeserializeOwned, Serialize};
-use ggez::{GameResult};
-use ron::{de::from_reader};
+use ggez::{GameResult, GameError};
+use ron::{de::from_str, ser::{to_string_pretty, PrettyConfig}};
+use crate::{Pattern, MAX_SPECIES};
 
+mod error;
+mod render;
 mod simulation;
 mod species;
 mod window;
 
+pub use error::*;
+pub use render::*;
 pub use simulation::*;
 pub use species::*;
 pub use  window::*;
 
 
+// Overrides the user config directory, e.g. `PHYSARUM_CONFIG_DIR=~/physarum`.
+pub const CONFIG_DIR_ENV: &str = "PHYSARUM_CONFIG_DIR";
+
+// Defaults compiled into the binary, used when no config directory is found.
+// Keep in sync with the files in `config/`.
+const BUNDLED_CONFIGS: &[(&str, &str)] = &[
+    ("simulation", include_str!("../../config/simulation.ron")),
+    ("window", include_str!("../../config/window.ron")),
+    ("render", include_str!("../../config/render.ron")),
+    ("species/0", include_str!("../../config/species/0.ron")),
+    ("species/1", include_str!("../../config/species/1.ron")),
+];
+
+// Set from `--config` before anything is loaded.
+static CONFIG_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+// Where every config, species and mask is read from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigRoot {
+    Directory(PathBuf),
+    Bundled,
+}
+
+impl ConfigRoot {
+    pub fn read(&self, name: &str) -> GameResult<String> {
+        return match self {
+            ConfigRoot::Directory(dir) => {
+                let path = dir.join(format!("{}.ron", name));
+                fs::read_to_string(&path).map_err(|e| GameError::ResourceLoadError(format!("Failed to open config at path {}: {}", path.display(), e)))
+            },
+            ConfigRoot::Bundled => BUNDLED_CONFIGS.iter()
+                .find(|(bundled, _)| *bundled == name)
+                .map(|(_, contents)| contents.to_string())
+                .ok_or_else(|| GameError::ResourceLoadError(format!("No bundled config named {}", name))),
+        };
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        return match self {
+            ConfigRoot::Directory(dir) => dir.join(format!("{}.ron", name)).is_file(),
+            ConfigRoot::Bundled => BUNDLED_CONFIGS.iter().any(|(bundled, _)| *bundled == name),
+        };
+    }
+
+    // Species config names in load order, the file order is the species index.
+    pub fn species_names(&self) -> GameResult<Vec<String>> {
+        let mut names: Vec<String> = match self {
+            ConfigRoot::Directory(dir) => {
+                let mut names = Vec::new();
+
+                for entry in fs::read_dir(dir.join("species"))? {
+                    let path = entry?.path();
+
+                    if path.extension().is_some_and(|ext| ext == "ron") {
+                        names.push(path.file_stem().unwrap().to_string_lossy().into_owned());
+                    }
+                }
+
+                names
+            },
+            ConfigRoot::Bundled => BUNDLED_CONFIGS.iter()
+                .filter_map(|(name, _)| name.strip_prefix("species/"))
+                .map(String::from)
+                .collect(),
+        };
+
+        names.sort();
+
+        return Ok(names);
+    }
+
+    // Path of a non RON resource such as a spawn mask.
+    pub fn path(&self, name: &str) -> GameResult<PathBuf> {
+        return match self {
+            ConfigRoot::Directory(dir) => Ok(dir.join(name)),
+            ConfigRoot::Bundled => Err(GameError::ResourceLoadError(format!("{} needs a config directory, pass --config or set {}", name, CONFIG_DIR_ENV))),
+        };
+    }
+}
+
+// Precedence: `--config`, then `PHYSARUM_CONFIG_DIR`, then the XDG config
+// directory if it holds a `simulation.ron`, then the bundled defaults.
+pub fn resolve_config_root(cli: Option<PathBuf>, env: Option<OsString>, xdg_config_home: Option<OsString>, home: Option<OsString>) -> ConfigRoot {
+    if let Some(dir) = cli { return ConfigRoot::Directory(dir); }
+
+    if let Some(dir) = env.filter(|dir| !dir.is_empty()) { return ConfigRoot::Directory(PathBuf::from(dir)); }
+
+    if let Some(dir) = user_config_dir(xdg_config_home, home).filter(|dir| dir.join("simulation.ron").is_file()) {
+        return ConfigRoot::Directory(dir);
+    }
+
+    return ConfigRoot::Bundled;
+}
+
+// `$XDG_CONFIG_HOME/physarum`, falling back to `~/.config/physarum`.
+pub fn user_config_dir(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    return match (xdg_config_home.filter(|dir| !dir.is_empty()), home) {
+        (Some(dir), _) => Some(PathBuf::from(dir).join("physarum")),
+        (None, Some(home)) => Some(PathBuf::from(home).join(".config").join("physarum")),
+        (None, None) => None,
+    };
+}
+
+pub fn set_config_dir(dir: Option<PathBuf>) {
+    *CONFIG_DIR_OVERRIDE.write().unwrap() = dir;
+}
+
+pub fn config_root() -> ConfigRoot {
+    let cli = CONFIG_DIR_OVERRIDE.read().unwrap().clone();
+
+    return resolve_config_root(cli, env::var_os(CONFIG_DIR_ENV), env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"));
+}
+
+// Finds `--config <dir>` among the command line arguments.
+pub fn config_dir_from_args(args: &[String]) -> GameResult<Option<PathBuf>> {
+    return match args.iter().position(|arg| arg == "--config") {
+        Some(index) => match args.get(index + 1) {
+            Some(dir) => Ok(Some(Path::new(dir).to_path_buf())),
+            None => Err(GameError::CustomError(String::from("Missing value for --config"))),
+        },
+        None => Ok(None),
+    };
+}
+
 pub fn load<T: Serialize + DeserializeOwned>(name: &str) -> GameResult<T> {
-    let path = format!("{}/config/{}.ron", env!("CARGO_MANIFEST_DIR"), name);
-    let file = File::open(&path).expect(&format!("Failed to open file at path: {}", path));
-    let config = match from_reader(file) {
-        Ok(x) => x,
-        Err(e) => {
-            println!("Failed to load {} config: {}", name, e);
-            std::process::exit(1);
+    return load_from(&config_root(), name);
+}
+
+pub fn load_from<T: Serialize + DeserializeOwned>(root: &ConfigRoot, name: &str) -> GameResult<T> {
+    let (config, _) = parse::<T>(root, name)?;
+
+    return Ok(config);
+}
+
+// `render.ron` is optional, config directories from before it existed render
+// with the defaults.
+pub fn load_render_config(root: &ConfigRoot) -> GameResult<RenderConfig> {
+    if !root.exists("render") { return Ok(RenderConfig::default()); }
+
+    return load_from(root, "render");
+}
+
+// Reads and deserializes `name`, returning the contents too so later errors can
+// be located in the file.
+pub fn parse<T: DeserializeOwned>(root: &ConfigRoot, name: &str) -> Result<(T, String), ConfigError> {
+    let file = format!("{}.ron", name);
+    let contents = root.read(name).map_err(|e| ConfigError { file: file.clone(), ..ConfigError::new(&e.to_string()) })?;
+
+    return match from_str(&contents) {
+        Ok(config) => Ok((config, contents)),
+        Err(e) => Err(ConfigError {
+            file,
+            position: Some((e.position.line, e.position.col)),
+            field: None,
+            message: e.code.to_string(),
+        }),
+    };
+}
+
+// Writes every config as RON into `root`, or into the user config directory when
+// running from the bundled defaults so the saved set is found on the next start.
+// Returns the directory written to.
+pub fn save_all(root: &ConfigRoot, simulation_config: &SimulationConfig, window_config: &WindowConfig, render_config: &RenderConfig, species_configs: &Vec<SpeciesConfig>) -> GameResult<PathBuf> {
+    let dir = match root {
+        ConfigRoot::Directory(dir) => dir.clone(),
+        ConfigRoot::Bundled => user_config_dir(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"))
+            .ok_or_else(|| GameError::ResourceLoadError(format!("No config directory to save to, pass --config or set {}", CONFIG_DIR_ENV)))?,
+    };
+
+    let names = root.species_names()?;
+
+    if names.len() != species_configs.len() {
+        return Err(GameError::ConfigError(format!("Found {} species configs on disk but {} are loaded", names.len(), species_configs.len())));
+    }
+
+    fs::create_dir_all(dir.join("species"))?;
+
+    save_to(&dir, "simulation", simulation_config)?;
+    save_to(&dir, "window", window_config)?;
+    save_to(&dir, "render", render_config)?;
+
+    for (name, species_config) in names.iter().zip(species_configs.iter()) {
+        save_to(&dir, &format!("species/{}", name), species_config)?;
+    }
+
+    return Ok(dir);
+}
+
+// Writes a fresh config directory with the species named by their index.
+// Refuses to write over an existing one.
+pub fn write_config_dir(dir: &Path, simulation_config: &SimulationConfig, window_config: &WindowConfig, render_config: &RenderConfig, species_configs: &Vec<SpeciesConfig>) -> GameResult {
+    if dir.join("simulation.ron").exists() || dir.join("species").exists() {
+        return Err(GameError::ConfigError(format!("{} already holds a config", dir.display())));
+    }
+
+    fs::create_dir_all(dir.join("species"))?;
+
+    save_to(dir, "simulation", simulation_config)?;
+    save_to(dir, "window", window_config)?;
+    save_to(dir, "render", render_config)?;
+
+    for (index, species_config) in species_configs.iter().enumerate() {
+        save_to(dir, &format!("species/{}", index), species_config)?;
+    }
+
+    return Ok(());
+}
+
+fn save_to<T: Serialize>(dir: &Path, name: &str, config: &T) -> GameResult {
+    let contents = to_string_pretty(config, PrettyConfig::new().struct_names(true)).map_err(|e| GameError::ConfigError(format!("Failed to encode {} config: {}", name, e)))?;
+    fs::write(dir.join(format!("{}.ron", name)), contents)?;
+
+    return Ok(());
+}
+
+// Parses and checks every config under `root`, collecting all problems instead
+// of stopping at the first.
+pub fn validate_all(root: &ConfigRoot) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+
+    match parse::<SimulationConfig>(root, "simulation") {
+        Ok((config, contents)) => {
+            errors.extend(config.validate().into_iter().map(|e| e.in_file("simulation.ron", &contents)));
+
+            if let Pattern::Image(name) = &config.pattern {
+                if !root.path(name).is_ok_and(|path| path.is_file()) {
+                    errors.push(ConfigError::field("pattern", &format!("mask image {} was not found", name)).in_file("simulation.ron", &contents));
+                }
+            }
+        },
+        Err(e) => errors.push(e),
+    }
+
+    match parse::<WindowConfig>(root, "window") {
+        Ok((config, contents)) => errors.extend(config.validate().into_iter().map(|e| e.in_file("window.ron", &contents))),
+        Err(e) => errors.push(e),
+    }
+
+    if root.exists("render") {
+        match parse::<RenderConfig>(root, "render") {
+            Ok((config, contents)) => errors.extend(config.validate().into_iter().map(|e| e.in_file("render.ron", &contents))),
+            Err(e) => errors.push(e),
         }
+    }
+
+    let names = match root.species_names() {
+        Ok(names) => names,
+        Err(e) => {
+            errors.push(ConfigError { file: String::from("species"), ..ConfigError::new(&e.to_string()) });
+            return errors;
+        },
     };
 
-    return Ok(config);
+    if names.is_empty() || names.len() > MAX_SPECIES {
+        errors.push(ConfigError { file: String::from("species"), ..ConfigError::new(&format!("found {} species configs, expected 1 to {}", names.len(), MAX_SPECIES)) });
+    }
+
+    for name in names.iter() {
+        let file = format!("species/{}.ron", name);
+
+        match parse::<SpeciesConfig>(root, &format!("species/{}", name)) {
+            Ok((config, contents)) => {
+                let mut species_errors = config.validate();
+
+                if config.interactions.len() > names.len() {
+                    species_errors.push(ConfigError::field("interactions", &format!("has {} entries but there are only {} species", config.interactions.len(), names.len())));
+                }
+
+                errors.extend(species_errors.into_iter().map(|e| e.in_file(&file, &contents)));
+            },
+            Err(e) => errors.push(e),
+        }
+    }
+
+    return errors;
 }
@@ -0,0 +1,212 @@
+use serde::{Serialize, Deserialize};
+use crate::{ColorMap, SpeciesBlend, TextureFilter, RenderMode, AgentShape, AgentColoring, ToneMap, ConfigError, check_range, gradient_stops, MAX_BLOOM_RADIUS};
+
+
+// Optional `render.ron`, the defaults match the plain additive species colours.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RenderConfig {
+    #[serde(default)]
+    pub color_map: ColorMap,
+    #[serde(default)]
+    pub blend: SpeciesBlend,
+    // Trail values are multiplied by `exposure` before colouring.
+    #[serde(default = "default_exposure")]
+    pub exposure: f32,
+    // Colours are raised to `1 / gamma` last, above 1 brightens faint trails.
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    // How the map is sampled when a cell covers more or less than a pixel.
+    #[serde(default)]
+    pub filter: TextureFilter,
+    // Letterboxes the map instead of stretching it to the window.
+    #[serde(default)]
+    pub keep_aspect: bool,
+    #[serde(default)]
+    pub mode: RenderMode,
+    #[serde(default)]
+    pub agent_shape: AgentShape,
+    #[serde(default)]
+    pub agent_coloring: AgentColoring,
+    // Only every `agent_subsample`th agent is drawn.
+    #[serde(default = "default_agent_subsample")]
+    pub agent_subsample: u32,
+    // Width of a drawn agent in pixels.
+    #[serde(default = "default_agent_size")]
+    pub agent_size: f32,
+    // Applied to the trail and agents after they are drawn.
+    #[serde(default)]
+    pub post: PostConfig,
+}
+
+impl RenderConfig {
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        check_range(&mut errors, "exposure", self.exposure, 0.0, f32::INFINITY);
+        if !self.gamma.is_finite() || self.gamma <= 0.0 {
+            errors.push(ConfigError::field("gamma", &format!("must be greater than 0, got {}", self.gamma)));
+        }
+
+        if self.agent_subsample == 0 { errors.push(ConfigError::field("agent_subsample", "must be at least 1")); }
+        if !self.agent_size.is_finite() || self.agent_size <= 0.0 {
+            errors.push(ConfigError::field("agent_size", &format!("must be greater than 0, got {}", self.agent_size)));
+        }
+
+        if let Err(message) = gradient_stops(&self.color_map) {
+            errors.push(ConfigError::field("color_map", &message));
+        }
+
+        errors.extend(self.post.validate());
+
+        return errors;
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> RenderConfig {
+        let render_config = RenderConfig {
+            color_map: ColorMap::Species,
+            blend: SpeciesBlend::Additive,
+            exposure: default_exposure(),
+            gamma: default_gamma(),
+            filter: TextureFilter::Nearest,
+            keep_aspect: false,
+            mode: RenderMode::Trail,
+            agent_shape: AgentShape::Point,
+            agent_coloring: AgentColoring::Species,
+            agent_subsample: default_agent_subsample(),
+            agent_size: default_agent_size(),
+            post: PostConfig::default(),
+        };
+
+        return render_config;
+    }
+}
+
+// The post processing chain. Every stage is off by default, which draws the
+// frame exactly as the colour map shades it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PostConfig {
+    #[serde(default)]
+    pub exposure: ExposureConfig,
+    #[serde(default)]
+    pub bloom: BloomConfig,
+    #[serde(default)]
+    pub tone_map: ToneMap,
+    #[serde(default)]
+    pub vignette: VignetteConfig,
+}
+
+impl PostConfig {
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let exposure = &self.exposure;
+
+        check_range(&mut errors, "post.exposure.compensation", exposure.compensation, -16.0, 16.0);
+        if !exposure.key.is_finite() || exposure.key <= 0.0 {
+            errors.push(ConfigError::field("post.exposure.key", &format!("must be greater than 0, got {}", exposure.key)));
+        }
+        check_range(&mut errors, "post.exposure.min_log_luminance", exposure.min_log_luminance, -32.0, 32.0);
+        if !(exposure.max_log_luminance > exposure.min_log_luminance && exposure.max_log_luminance <= 32.0) {
+            errors.push(ConfigError::field("post.exposure.max_log_luminance", &format!("must be above min_log_luminance and at most 32, got {}", exposure.max_log_luminance)));
+        }
+        check_range(&mut errors, "post.exposure.adaptation_rate", exposure.adaptation_rate, 0.0, f32::INFINITY);
+
+        check_range(&mut errors, "post.bloom.threshold", self.bloom.threshold, 0.0, f32::INFINITY);
+        check_range(&mut errors, "post.bloom.intensity", self.bloom.intensity, 0.0, f32::INFINITY);
+        if self.bloom.radius > MAX_BLOOM_RADIUS {
+            errors.push(ConfigError::field("post.bloom.radius", &format!("must be at most {}, got {}", MAX_BLOOM_RADIUS, self.bloom.radius)));
+        }
+
+        check_range(&mut errors, "post.vignette.strength", self.vignette.strength, 0.0, 1.0);
+        check_range(&mut errors, "post.vignette.radius", self.vignette.radius, 0.0, f32::INFINITY);
+        check_range(&mut errors, "post.vignette.softness", self.vignette.softness, 0.001, f32::INFINITY);
+
+        return errors;
+    }
+}
+
+// Colours are multiplied by `2^compensation` before tone mapping. With `auto`
+// they are first scaled so the average brightness of the lit pixels, measured
+// with a histogram of log2 luminance between `min_log_luminance` and
+// `max_log_luminance`, lands on `key`. `adaptation_rate` is how quickly the
+// window follows changes, 0 follows them instantly.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExposureConfig {
+    pub auto: bool,
+    pub compensation: f32,
+    pub key: f32,
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    pub adaptation_rate: f32,
+}
+
+impl Default for ExposureConfig {
+    fn default() -> ExposureConfig {
+        let exposure_config = ExposureConfig {
+            auto: false,
+            compensation: 0.0,
+            key: 0.18,
+            min_log_luminance: -10.0,
+            max_log_luminance: 4.0,
+            adaptation_rate: 1.5,
+        };
+
+        return exposure_config;
+    }
+}
+
+// Light above `threshold` is blurred at half resolution over `radius` pixels
+// and added back scaled by `intensity`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BloomConfig {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub intensity: f32,
+    pub radius: u32,
+}
+
+impl Default for BloomConfig {
+    fn default() -> BloomConfig {
+        let bloom_config = BloomConfig { enabled: false, threshold: 1.0, intensity: 0.5, radius: 8 };
+
+        return bloom_config;
+    }
+}
+
+// Darkens by up to `strength` towards the corners, starting `radius` out from
+// the centre (1 is a corner) and fading in over `softness`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VignetteConfig {
+    pub enabled: bool,
+    pub strength: f32,
+    pub radius: f32,
+    pub softness: f32,
+}
+
+impl Default for VignetteConfig {
+    fn default() -> VignetteConfig {
+        let vignette_config = VignetteConfig { enabled: false, strength: 0.4, radius: 0.5, softness: 0.5 };
+
+        return vignette_config;
+    }
+}
+
+fn default_exposure() -> f32 {
+    return 1.0;
+}
+
+fn default_gamma() -> f32 {
+    return 1.0;
+}
+
+fn default_agent_subsample() -> u32 {
+    return 1;
+}
+
+fn default_agent_size() -> f32 {
+    return 2.0;
+}
@@ -1,8 +1,107 @@
 use serde::{Serialize, Deserialize};
+use crate::{Boundary, Pattern, Heading, DiffusionKernel, ConfigError, check_range};
 
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SimulationConfig {
     pub agent_count: u32,
+    // Drives agent initialisation and the shader RNG, the same seed reproduces a run.
+    // Bit for bit on the CPU backend, the GPU only on the same device and driver.
+    #[serde(default)]
+    pub seed: u64,
+    // Map size in cells, configs from before it was split from the window size
+    // get the old 800x800 window.
+    #[serde(default = "default_map_size")]
+    pub width: i32,
+    #[serde(default = "default_map_size")]
+    pub height: i32,
+    // Fraction of each cell replaced by its blurred neighbourhood per step.
+    // Before `decay_rate` existed this was the evaporation, with a full blur.
     pub diffusion_rate: f32,
+    // Fraction of the trail that evaporates per step. Required so older configs
+    // don't silently lose their decay, their `diffusion_rate: x` becomes
+    // `diffusion_rate: 1.0, decay_rate: x`.
+    pub decay_rate: f32,
+    #[serde(default)]
+    pub diffusion_kernel: DiffusionKernel,
+    #[serde(default)]
+    pub boundary: Boundary,
+    #[serde(default)]
+    pub pattern: Pattern,
+    // Overrides the pattern's own heading distribution.
+    #[serde(default)]
+    pub heading: Option<Heading>,
+    // Simulated seconds per step, independent of the frame rate.
+    #[serde(default = "default_time_step")]
+    pub time_step: f32,
+    // Steps run per rendered frame.
+    #[serde(default = "default_substeps")]
+    pub substeps: u32,
+    // Multiplies the number of steps per frame, below 1 for slow motion.
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f32,
+    // Radius of the circular patterns and of each cluster, in units of the map width.
+    #[serde(default = "default_spawn_radius")]
+    pub spawn_radius: f32,
+}
+
+// Most agents or map cells one dispatch of 32 wide workgroups covers, wgpu allows
+// at most 65535 workgroups per dimension.
+pub const MAX_INVOCATIONS: u32 = 65535 * 32;
+
+impl SimulationConfig {
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.agent_count == 0 { errors.push(ConfigError::field("agent_count", "must be at least 1")); }
+        if self.width <= 0 { errors.push(ConfigError::field("width", &format!("must be at least 1, got {}", self.width))); }
+        if self.height <= 0 { errors.push(ConfigError::field("height", &format!("must be at least 1, got {}", self.height))); }
+        if self.agent_count > MAX_INVOCATIONS {
+            errors.push(ConfigError::field("agent_count", &format!("must be at most {}, got {}", MAX_INVOCATIONS, self.agent_count)));
+        }
+        if self.width as i64 * self.height as i64 > MAX_INVOCATIONS as i64 {
+            errors.push(ConfigError::field("width", &format!("times height must be at most {} cells, got {}x{}", MAX_INVOCATIONS, self.width, self.height)));
+        }
+        if self.substeps == 0 { errors.push(ConfigError::field("substeps", "must be at least 1")); }
+
+        check_range(&mut errors, "diffusion_rate", self.diffusion_rate, 0.0, 1.0);
+        check_range(&mut errors, "decay_rate", self.decay_rate, 0.0, 1.0);
+        if !self.time_step.is_finite() || self.time_step <= 0.0 {
+            errors.push(ConfigError::field("time_step", &format!("must be greater than 0, got {}", self.time_step)));
+        }
+        check_range(&mut errors, "time_scale", self.time_scale, 0.0, f32::INFINITY);
+        check_range(&mut errors, "spawn_radius", self.spawn_radius, 0.0, f32::INFINITY);
+
+        if let DiffusionKernel::SeparableGaussian(radius) = self.diffusion_kernel {
+            if radius == 0 { errors.push(ConfigError::field("diffusion_kernel", "SeparableGaussian radius must be at least 1")); }
+        }
+
+        return errors;
+    }
+
+    // Whether switching to `other` changes buffer sizes. Everything else can be
+    // uploaded in place, spawn settings take effect on the next reset.
+    pub fn requires_rebuild(&self, other: &SimulationConfig) -> bool {
+        return self.agent_count != other.agent_count || self.width != other.width || self.height != other.height;
+    }
+}
+
+fn default_map_size() -> i32 {
+    return 800;
+}
+
+fn default_spawn_radius() -> f32 {
+    return 0.5;
+}
+
+fn default_time_step() -> f32 {
+    return 1.0 / 60.0;
+}
+
+fn default_substeps() -> u32 {
+    return 1;
+}
+
+fn default_time_scale() -> f32 {
+    return 1.0;
 }
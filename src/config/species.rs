@@ -1,9 +1,13 @@
 use serde::{Serialize, Deserialize};
+use ggez::{GameResult, GameError};
+use crate::{SensorKernel, ConfigError, check_range, parse_hex_color, MAX_SPECIES};
 
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SpeciesConfig {
     pub sensor_size: f32,
+    #[serde(default)]
+    pub sensor_kernel: SensorKernel,
     pub sensor_angle: f32,
     pub sensor_distance: f32,
     pub turn_speed: f32,
@@ -12,7 +16,72 @@ pub struct SpeciesConfig {
     pub left_bias: f32,
     pub right_bias: f32,
     pub weight: f32,
+    #[serde(default)]
     pub color_r: f32,
+    #[serde(default)]
     pub color_g: f32,
+    #[serde(default)]
     pub color_b: f32,
+    // A hex string such as "#ff00ff", used instead of `color_r`, `color_g` and `color_b` when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    // How strongly this species follows each species' trail, indexed by species.
+    // Negative values repel. Defaults to following only its own trail.
+    #[serde(default)]
+    pub interactions: Vec<f32>,
+}
+
+impl SpeciesConfig {
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        check_range(&mut errors, "sensor_size", self.sensor_size, 0.0, f32::INFINITY);
+        check_range(&mut errors, "sensor_angle", self.sensor_angle, -360.0, 360.0);
+        check_range(&mut errors, "sensor_distance", self.sensor_distance, 0.0, f32::INFINITY);
+        check_range(&mut errors, "turn_speed", self.turn_speed, 0.0, f32::INFINITY);
+        check_range(&mut errors, "move_speed", self.move_speed, 0.0, f32::INFINITY);
+        check_range(&mut errors, "weight", self.weight, 0.0, f32::INFINITY);
+        check_range(&mut errors, "color_r", self.color_r, 0.0, f32::INFINITY);
+        check_range(&mut errors, "color_g", self.color_g, 0.0, f32::INFINITY);
+        check_range(&mut errors, "color_b", self.color_b, 0.0, f32::INFINITY);
+
+        for (field, value) in [("forward_bias", self.forward_bias), ("left_bias", self.left_bias), ("right_bias", self.right_bias)] {
+            if !value.is_finite() { errors.push(ConfigError::field(field, "must be a finite number")); }
+        }
+
+        if let Some(Err(message)) = self.color.as_ref().map(|hex| parse_hex_color(hex)) {
+            errors.push(ConfigError::field("color", &message));
+        }
+        // Both colour forms are optional, a species with neither would draw black.
+        if self.color.is_none() && [self.color_r, self.color_g, self.color_b] == [0.0; 3] {
+            errors.push(ConfigError::field("color", "is missing, set `color` or `color_r`, `color_g` and `color_b`"));
+        }
+
+        if self.interactions.len() > MAX_SPECIES {
+            errors.push(ConfigError::field("interactions", &format!("has {} entries, at most {} are supported", self.interactions.len(), MAX_SPECIES)));
+        }
+        if self.interactions.iter().any(|value| !value.is_finite()) {
+            errors.push(ConfigError::field("interactions", "must all be finite numbers"));
+        }
+
+        return errors;
+    }
+
+    pub fn rgb(&self) -> GameResult<[f32; 3]> {
+        return match &self.color {
+            Some(hex) => parse_hex_color(hex).map_err(GameError::ConfigError),
+            None => Ok([self.color_r, self.color_g, self.color_b]),
+        };
+    }
+
+    // Gives every species an explicit interaction, keeping the implicit "follow
+    // only your own trail" default when none were set.
+    pub fn expand_interactions(&mut self, index: usize, species_count: usize) {
+        if self.interactions.is_empty() && index < species_count {
+            self.interactions = vec![0.0; species_count];
+            self.interactions[index] = 1.0;
+        }
+
+        self.interactions.resize(species_count, 0.0);
+    }
 }
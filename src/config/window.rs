@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use ggez::{graphics::Color};
+use crate::{ConfigError};
 
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -12,3 +13,14 @@ pub struct WindowConfig {
     pub fullscreen: bool,
     pub auto_run: bool,
 }
+
+impl WindowConfig {
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.width <= 0 { errors.push(ConfigError::field("width", &format!("must be at least 1, got {}", self.width))); }
+        if self.height <= 0 { errors.push(ConfigError::field("height", &format!("must be at least 1, got {}", self.height))); }
+
+        return errors;
+    }
+}
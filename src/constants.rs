@@ -1,23 +1,38 @@
-use crate::{WindowConfig, SimulationConfig};
+use crate::{SimulationConfig};
 use ggez::{GameResult};
 
 
 #[repr(C)]
 #[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy)]
 pub struct Constants {
-    window_height: f32,
-    window_width: f32,
+    height: f32,
+    width: f32,
     diffusion_rate: f32,
+    boundary: u32,
+    seed: u32,
+    decay_rate: f32,
+    diffusion_kernel: u32,
+    diffusion_radius: u32,
 }
 
 impl Constants {
-    pub fn new(window_config: &WindowConfig, simulation_config: &SimulationConfig) -> GameResult<Constants> {
+    pub fn new(simulation_config: &SimulationConfig) -> GameResult<Constants> {
         let constants = Constants {
-            window_height: window_config.height as f32,
-            window_width: window_config.width as f32,
+            height: simulation_config.height as f32,
+            width: simulation_config.width as f32,
             diffusion_rate: simulation_config.diffusion_rate,
+            boundary: simulation_config.boundary as u32,
+            seed: shader_seed(simulation_config.seed),
+            decay_rate: simulation_config.decay_rate,
+            diffusion_kernel: simulation_config.diffusion_kernel.index(),
+            diffusion_radius: simulation_config.diffusion_kernel.radius(),
         };
 
         return Ok(constants);
     }
 }
+
+// Folds the 64 bit config seed into the 32 bits the shader RNG works with.
+pub fn shader_seed(seed: u64) -> u32 {
+    return (seed as u32) ^ ((seed >> 32) as u32);
+}
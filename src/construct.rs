@@ -1,24 +1,34 @@
-use std::{mem, fs, fs::DirEntry, num::{NonZeroU32, NonZeroU64}};
-use ggez::{Context, GameResult};
-use crate::{util, Agent, Trail, Constants, SimulationConfig, WindowConfig, Param, Storage, ComputeProgram, RenderProgram, config, SpeciesConfig, Species};
-use walkdir::WalkDir;
+use std::{mem, num::{NonZeroU32, NonZeroU64}};
+use ggez::{Context, GameResult, GameError};
+use crate::{util, Agent, Trail, Constants, SimulationConfig, Param, Brush, Camera, Palette, AgentStyle, PostSettings, HISTOGRAM_BINS, Storage, TextureStorage, MAP_TEXTURE_FORMAT, HDR_TEXTURE_FORMAT, CAPTURE_TEXTURE_FORMAT, ComputeProgram, RenderProgram, config, SpeciesConfig, Species, MAX_SPECIES, Pattern, Mask};
+use rand::{rngs::StdRng, SeedableRng};
 
 
+pub fn load_species_configs() -> GameResult<Vec<SpeciesConfig>> {
+  let mut configs = Vec::new();
+  let root = config::config_root();
+  let names = root.species_names()?;
+
+  if names.len() > MAX_SPECIES {
+      return Err(GameError::ConfigError(format!("Found {} species configs, at most {} are supported", names.len(), MAX_SPECIES)));
+  }
+
+  for name in names.iter() {
+      configs.push(config::load_from::<SpeciesConfig>(&root, &format!("species/{}", name))?);
+  }
+
+  return Ok(configs);
+}
+
 pub fn construct_species(species_count: &mut usize) -> GameResult<Vec<Species>> {
-  let mut data = Vec::new();
-  let path = format!("{}/config/species/", env!("CARGO_MANIFEST_DIR"));
-  let mut dir: Vec<DirEntry> = fs::read_dir(path)?.map(|r| r.unwrap()).collect();
+  return construct_species_from(&load_species_configs()?, species_count);
+}
 
-  dir.sort_by_key(|d| d.path());
+pub fn construct_species_from(configs: &Vec<SpeciesConfig>, species_count: &mut usize) -> GameResult<Vec<Species>> {
+  let mut data = Vec::new();
 
-  for file in dir {            
-      let mut name = file.file_name().into_string().unwrap();
-      
-      let offset = name.find('.').unwrap_or(name.len());
-      name.drain(offset..);
-      
-      let config = config::load::<SpeciesConfig>(&format!("species/{}", &name))?;
-      let species = Species::new(config)?;
+  for (index, config) in configs.iter().enumerate() {
+      let species = Species::new(config.clone(), index)?;
       
       *species_count += 1;
       
@@ -30,35 +40,62 @@ pub fn construct_species(species_count: &mut usize) -> GameResult<Vec<Species>>
 
 pub fn construct_agents(simulation_config: &SimulationConfig) -> GameResult<Vec<Agent>> {
   let mut agents = Vec::new();
-  let mut rng = rand::thread_rng();
+  let mut rng = StdRng::seed_from_u64(simulation_config.seed);
 
-  let path = "./config/species";
-  let species_count = WalkDir::new(path).into_iter().count() as u32 - 1;
+  let root = config::config_root();
+  let species_count = root.species_names()?.len() as u32;
+
+  let aspect = simulation_config.width as f32 / simulation_config.height as f32;
+  let radius = simulation_config.spawn_radius;
+  let heading = simulation_config.heading.unwrap_or(simulation_config.pattern.default_heading());
+
+  let mask = match &simulation_config.pattern {
+      Pattern::Image(name) => Some(Mask::load(&root.path(name)?.to_string_lossy())?),
+      _ => None,
+  };
 
   for _ in 0..simulation_config.agent_count {
       let agent = Agent::default()?
-          .random_angle(&mut rng)?
-          .random_position(&mut rng)?
-          // .with_species(0)?;
           .random_species(&mut rng, species_count)?;
+
+      let agent = match &simulation_config.pattern {
+          Pattern::Random       => agent.random_position(&mut rng)?,
+          Pattern::Center       => agent.with_position(0.0, 0.0)?,
+          Pattern::Spherical    => agent.random_position_in_disc(&mut rng, [0.0, 0.0], radius, aspect)?,
+          Pattern::Ring         => agent.random_position_on_ring(&mut rng, radius, aspect)?,
+          Pattern::InwardCircle => agent.random_position_in_disc(&mut rng, [0.0, 0.0], radius, aspect)?,
+          Pattern::Clusters     => {
+              // One cluster per species, spaced evenly around the centre.
+              let angle = agent.species as f32 / species_count as f32 * std::f32::consts::TAU;
+              let center = [angle.cos() * 0.5, angle.sin() * 0.5 * aspect];
+              agent.random_position_in_disc(&mut rng, center, radius / 2.0, aspect)?
+          },
+          Pattern::Image(_)     => {
+              let position = mask.as_ref().unwrap().random_position(&mut rng);
+              agent.with_position(position[0], position[1])?
+          },
+      };
+
+      let agent = agent.with_heading(&mut rng, heading, aspect)?;
+
       agents.push(agent);
   }
 
   return Ok(agents);
 }
 
-pub fn construct_trail_map(window_config: &WindowConfig) -> GameResult<Vec<Trail>> {
+pub fn construct_trail_map(simulation_config: &SimulationConfig) -> GameResult<Vec<Trail>> {
   let mut trail_map = Vec::new();
 
-  for y in 0..window_config.height {
-      for x in 0..window_config.width {
-          let xb = (window_config.width / 2) as f32;
-          let yb = (window_config.height / 2) as f32;
+  for y in 0..simulation_config.height {
+      for x in 0..simulation_config.width {
+          let xb = simulation_config.width as f32 / 2.0;
+          let yb = simulation_config.height as f32 / 2.0;
           
           let x_pos = (x as f32 - xb) / xb;
           let y_pos = (y as f32 - yb) / yb;
 
-          let trail = Trail::new([x_pos, y_pos], 0.0)?;
+          let trail = Trail::new([x_pos, y_pos], [0.0; MAX_SPECIES])?;
 
           trail_map.push(trail);
       }
@@ -67,10 +104,10 @@ pub fn construct_trail_map(window_config: &WindowConfig) -> GameResult<Vec<Trail
   return Ok(trail_map);
 }
 
-pub fn construct_constants_storage(device: &wgpu::Device, window_config: &WindowConfig, simulation_config: &SimulationConfig) -> GameResult<Storage> {
+pub fn construct_constants_storage(device: &wgpu::Device, simulation_config: &SimulationConfig) -> GameResult<Storage> {
   let size = mem::size_of::<Constants>();
-  let data = vec![Constants::new(window_config, simulation_config)?];
-  let buffer = util::construct_buffer_init(device, "Constants Buffer", &data, wgpu::BufferUsages::UNIFORM)?;
+  let data = vec![Constants::new(simulation_config)?];
+  let buffer = util::construct_buffer_init(device, "Constants Buffer", &data, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)?;
 
   let storage = Storage { size, buffer };
 
@@ -80,7 +117,7 @@ pub fn construct_constants_storage(device: &wgpu::Device, window_config: &Window
 pub fn construct_agent_storage(device: &wgpu::Device, simulation_config: &SimulationConfig) -> GameResult<Storage> {
   let size = mem::size_of::<Agent>() * simulation_config.agent_count as usize;
   let data = construct_agents(simulation_config)?;
-  let buffer = util::construct_buffer_init(device, "Agent Buffer", &data, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST)?;
+  let buffer = util::construct_buffer_init(device, "Agent Buffer", &data, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST)?;
 
   let storage = Storage { size, buffer };
 
@@ -97,14 +134,24 @@ pub fn construct_param_storage(device: &wgpu::Device) -> GameResult<Storage> {
   return Ok(storage);
 }
 
-pub fn construct_map_storages(device: &wgpu::Device, window_config: &WindowConfig) -> GameResult<Vec<Storage>> {
-  let size = mem::size_of::<Trail>() * window_config.width as usize * window_config.height as usize;
-  let data = construct_trail_map(window_config)?;
+pub fn construct_param_staging_storage(device: &wgpu::Device, step_count: usize) -> GameResult<Storage> {
+  let size = mem::size_of::<Param>() * step_count;
+  let data = vec![Param::default(); step_count];
+  let buffer = util::construct_buffer_init(device, "Param Staging Buffer", &data, wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+pub fn construct_map_storages(device: &wgpu::Device, simulation_config: &SimulationConfig) -> GameResult<Vec<Storage>> {
+  let size = mem::size_of::<Trail>() * simulation_config.width as usize * simulation_config.height as usize;
+  let data = construct_trail_map(simulation_config)?;
 
   let mut storages = Vec::new();
 
   for i in 0..2 {
-      let buffer = util::construct_buffer_init(device, &format!("Map Buffer {}", i), &data, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST)?;
+      let buffer = util::construct_buffer_init(device, &format!("Map Buffer {}", i), &data, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST)?;
       let storage = Storage { size, buffer };
 
       storages.push(storage);
@@ -113,19 +160,76 @@ pub fn construct_map_storages(device: &wgpu::Device, window_config: &WindowConfi
   return Ok(storages);
 }
 
-pub fn construct_species_storage(device: &wgpu::Device) -> GameResult<Storage> {
-  let mut species_count = 0;
-  let data: Vec<Species> = construct_species(&mut species_count)?;
+pub fn construct_deposit_storage(device: &wgpu::Device, simulation_config: &SimulationConfig) -> GameResult<Storage> {
+  let size = mem::size_of::<u32>() * simulation_config.width as usize * simulation_config.height as usize * MAX_SPECIES;
+  let data = vec![0u32; size / mem::size_of::<u32>()];
+  let buffer = util::construct_buffer_init(device, "Deposit Buffer", &data, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+pub fn construct_blur_storage(device: &wgpu::Device, simulation_config: &SimulationConfig) -> GameResult<Storage> {
+  let size = mem::size_of::<[f32; MAX_SPECIES]>() * simulation_config.width as usize * simulation_config.height as usize;
+  let data = vec![[0.0f32; MAX_SPECIES]; simulation_config.width as usize * simulation_config.height as usize];
+  let buffer = util::construct_buffer_init(device, "Blur Buffer", &data, wgpu::BufferUsages::STORAGE)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+pub fn construct_brush_storage(device: &wgpu::Device) -> GameResult<Storage> {
+  let size = mem::size_of::<Brush>();
+  let data = vec![Brush::default()];
+  let buffer = util::construct_buffer_init(device, "Brush Buffer", &data, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+pub fn construct_camera_storage(device: &wgpu::Device, camera: &Camera) -> GameResult<Storage> {
+  let size = mem::size_of::<Camera>();
+  let data = vec![*camera];
+  let buffer = util::construct_buffer_init(device, "Camera Buffer", &data, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+pub fn construct_palette_storage(device: &wgpu::Device, palette: &Palette) -> GameResult<Storage> {
+  let size = mem::size_of::<Palette>();
+  let data = vec![*palette];
+  let buffer = util::construct_buffer_init(device, "Palette Buffer", &data, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+pub fn construct_agent_style_storage(device: &wgpu::Device, agent_style: &AgentStyle) -> GameResult<Storage> {
+  let size = mem::size_of::<AgentStyle>();
+  let data = vec![*agent_style];
+  let buffer = util::construct_buffer_init(device, "Agent Style Buffer", &data, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
 
-  let size = mem::size_of::<Species>() * species_count as usize;
-  let buffer = util::construct_buffer_init(device, &format!("Species Buffer"), &data, wgpu::BufferUsages::STORAGE)?;
+pub fn construct_species_storage(device: &wgpu::Device, data: &Vec<Species>) -> GameResult<Storage> {
+  let size = mem::size_of::<Species>() * data.len();
+  let buffer = util::construct_buffer_init(device, &format!("Species Buffer"), data, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST)?;
   
   let storage = Storage{ size, buffer };
   
   return Ok(storage);
 }
 
-pub fn construct_compute_agent_program(ctx: &mut Context, simulation_config: &SimulationConfig, constants_storage: &Storage, agent_storage: &Storage, param_storage: &Storage, map_storages: &Vec<Storage>, species_storage: &Storage) -> GameResult<ComputeProgram> {
+pub fn construct_compute_agent_program(ctx: &mut Context, simulation_config: &SimulationConfig, constants_storage: &Storage, agent_storage: &Storage, param_storage: &Storage, map_storages: &Vec<Storage>, species_storage: &Storage, deposit_storage: &Storage) -> GameResult<ComputeProgram> {
   let device = &ctx.gfx.wgpu().device;
 
   let compute_shader = util::construct_shader_module(device, "Compute Shader", include_str!("shaders/update_agents.wgsl"))?;
@@ -185,12 +289,22 @@ pub fn construct_compute_agent_program(ctx: &mut Context, simulation_config: &Si
           binding: 5,
           visibility: wgpu::ShaderStages::COMPUTE,
           ty: wgpu::BindingType::Buffer {
-              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
               has_dynamic_offset: false,
               min_binding_size: wgpu::BufferSize::new(map_storages[0].size as _),
           },
           count: None,
       },
+      wgpu::BindGroupLayoutEntry {
+          binding: 6,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(deposit_storage.size as _),
+          },
+          count: None,
+      },
   ];
 
   let compute_bind_group_layout = util::construct_bind_group_layout(device, "Compute Agent Bind Group Layout", compute_bind_group_entries)?;
@@ -228,6 +342,10 @@ pub fn construct_compute_agent_program(ctx: &mut Context, simulation_config: &Si
                   binding: 5,
                   resource: map_storages[(i + 1) % 2].buffer.as_entire_binding(),
               },
+              wgpu::BindGroupEntry {
+                  binding: 6,
+                  resource: deposit_storage.buffer.as_entire_binding(),
+              },
           ],
       });
 
@@ -240,7 +358,9 @@ pub fn construct_compute_agent_program(ctx: &mut Context, simulation_config: &Si
   return Ok(compute_agent_program);
 }
 
-pub fn construct_compute_map_program(ctx: &mut Context, window_config: &WindowConfig, map_storages: &Vec<Storage>, constants_storage: &Storage, param_storage: &Storage) -> GameResult<ComputeProgram> {
+// `entry_point` is `main` for the diffusion pass or `blur_horizontal` for the first
+// pass of the separable Gaussian.
+pub fn construct_compute_map_program(ctx: &mut Context, simulation_config: &SimulationConfig, map_storages: &Vec<Storage>, constants_storage: &Storage, param_storage: &Storage, deposit_storage: &Storage, blur_storage: &Storage, entry_point: &str) -> GameResult<ComputeProgram> {
   let device = &ctx.gfx.wgpu().device;
 
   let compute_map_shader = util::construct_shader_module(device, "Compute Map Shader", include_str!("shaders/update_map.wgsl"))?;
@@ -286,11 +406,31 @@ pub fn construct_compute_map_program(ctx: &mut Context, window_config: &WindowCo
           },
           count: None,
       },
+      wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(deposit_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 5,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(blur_storage.size as _),
+          },
+          count: None,
+      },
   ];
 
   let compute_map_bind_group_layout = util::construct_bind_group_layout(device, "Compute Map Bind Group Layout", compute_map_bind_group_entries)?;
   let compute_map_pipeline_layout = util::construct_pipeline_layout(device, "Compute Map Pipeline Layout", &vec![&compute_map_bind_group_layout], &vec![])?;
-  let compute_map_pipeline = util::construct_compute_pipeline(device, "Compute Map Pipeline", Some(&compute_map_pipeline_layout), &compute_map_shader, "main")?;
+  let compute_map_pipeline = util::construct_compute_pipeline(device, "Compute Map Pipeline", Some(&compute_map_pipeline_layout), &compute_map_shader, entry_point)?;
 
   let mut compute_map_bind_groups = Vec::new();
 
@@ -315,19 +455,121 @@ pub fn construct_compute_map_program(ctx: &mut Context, window_config: &WindowCo
                   binding: 3,
                   resource: map_storages[(i + 1) % 2].buffer.as_entire_binding(),
               },
+              wgpu::BindGroupEntry {
+                  binding: 4,
+                  resource: deposit_storage.buffer.as_entire_binding(),
+              },
+              wgpu::BindGroupEntry {
+                  binding: 5,
+                  resource: blur_storage.buffer.as_entire_binding(),
+              },
           ],
       });
 
       compute_map_bind_groups.push(compute_map_bind_group);
   }
 
-  let work_group_count = ((window_config.width * window_config.height) as f32 / 32.0) as u32;
+  let work_group_count = ((simulation_config.width * simulation_config.height) as f32 / 32.0).ceil() as u32;
   let compute_map_program = ComputeProgram::new(compute_map_pipeline, compute_map_bind_groups, (work_group_count, 1, 1))?;
 
   return Ok(compute_map_program);
 }
 
-pub fn construct_render_map_program(ctx: &mut Context, species_storage: &Storage) -> GameResult<RenderProgram> {
+pub fn construct_map_texture(device: &wgpu::Device, simulation_config: &SimulationConfig) -> GameResult<TextureStorage> {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Map Texture"),
+      size: wgpu::Extent3d { width: simulation_config.width as u32, height: simulation_config.height as u32, depth_or_array_layers: 1 },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: MAP_TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+  let texture_storage = TextureStorage { texture, view };
+
+  return Ok(texture_storage);
+}
+
+// Writes the trail values of the current map into `map_texture`, one invocation
+// per cell. Bind group `i` reads `map_storages[i]`.
+pub fn construct_compute_texture_program(ctx: &mut Context, simulation_config: &SimulationConfig, constants_storage: &Storage, map_storages: &Vec<Storage>, map_texture: &TextureStorage) -> GameResult<ComputeProgram> {
+  let device = &ctx.gfx.wgpu().device;
+
+  let texture_shader = util::construct_shader_module(device, "Map Texture Shader", include_str!("shaders/map_texture.wgsl"))?;
+
+  let texture_bind_group_entries = &[
+      wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(constants_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(map_storages[0].size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::StorageTexture {
+              access: wgpu::StorageTextureAccess::WriteOnly,
+              format: MAP_TEXTURE_FORMAT,
+              view_dimension: wgpu::TextureViewDimension::D2,
+          },
+          count: None,
+      },
+  ];
+
+  let texture_bind_group_layout = util::construct_bind_group_layout(device, "Map Texture Bind Group Layout", texture_bind_group_entries)?;
+  let texture_pipeline_layout = util::construct_pipeline_layout(device, "Map Texture Pipeline Layout", &vec![&texture_bind_group_layout], &vec![])?;
+  let texture_pipeline = util::construct_compute_pipeline(device, "Map Texture Pipeline", Some(&texture_pipeline_layout), &texture_shader, "main")?;
+
+  let mut texture_bind_groups = Vec::new();
+
+  for i in 0..2 {
+      let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some(&format!("Map Texture Bind Group {}", i)),
+          layout: &texture_bind_group_layout,
+          entries: &[
+              wgpu::BindGroupEntry {
+                  binding: 0,
+                  resource: constants_storage.buffer.as_entire_binding(),
+              },
+              wgpu::BindGroupEntry {
+                  binding: 1,
+                  resource: map_storages[i].buffer.as_entire_binding(),
+              },
+              wgpu::BindGroupEntry {
+                  binding: 2,
+                  resource: wgpu::BindingResource::TextureView(&map_texture.view),
+              },
+          ],
+      });
+
+      texture_bind_groups.push(texture_bind_group);
+  }
+
+  let work_group_count = ((simulation_config.width * simulation_config.height) as f32 / 32.0).ceil() as u32;
+  let texture_program = ComputeProgram::new(texture_pipeline, texture_bind_groups, (work_group_count, 1, 1))?;
+
+  return Ok(texture_program);
+}
+
+// Draws the map texture with one full screen triangle, the fragment shader maps
+// each pixel through the camera so the window and map sizes are independent.
+pub fn construct_render_map_program(ctx: &mut Context, species_storage: &Storage, camera_storage: &Storage, palette_storage: &Storage, map_texture: &TextureStorage) -> GameResult<RenderProgram> {
   let device = &ctx.gfx.wgpu().device;
 
   let render_shader = util::construct_shader_module(device, "Render Map Shader", include_str!("shaders/render_map.wgsl"))?;
@@ -342,6 +584,48 @@ pub fn construct_render_map_program(ctx: &mut Context, species_storage: &Storage
           },
           count: None,
       },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(camera_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(palette_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 5,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+      },
   ];
   let render_map_bind_group_layout = util::construct_bind_group_layout(device, "Render Map Bind Group Layout", render_map_bind_group_entries)?;
   let pipeline_layout = util::construct_pipeline_layout(device, "Render Map Pipeline Layout", &vec![&render_map_bind_group_layout], &vec![])?;
@@ -352,19 +636,13 @@ pub fn construct_render_map_program(ctx: &mut Context, species_storage: &Storage
       wgpu::VertexState {
           module: &render_shader,
           entry_point: "main_vs",
-          buffers: &[
-              wgpu::VertexBufferLayout {
-                  array_stride: mem::size_of::<Agent>() as u64,
-                  step_mode: wgpu::VertexStepMode::Instance,
-                  attributes: &wgpu::vertex_attr_array![0 => Float32, 1 => Float32, 2 => Float32, 3 => Uint32],
-              },
-          ],
+          buffers: &[],
       },
       Some(wgpu::FragmentState {
           module: &render_shader,
           entry_point: "main_fs",
           targets: &[wgpu::ColorTargetState {
-                  format: ctx.gfx.surface_format(),
+                  format: HDR_TEXTURE_FORMAT,
                   blend: Some(wgpu::BlendState{
                       color: wgpu::BlendComponent{
                           src_factor: wgpu::BlendFactor::SrcAlpha,
@@ -379,7 +657,7 @@ pub fn construct_render_map_program(ctx: &mut Context, species_storage: &Storage
       None,
       wgpu::MultisampleState::default(),
       wgpu::PrimitiveState {
-          topology: wgpu::PrimitiveTopology::PointList,
+          topology: wgpu::PrimitiveTopology::TriangleList,
           strip_index_format: None,
           front_face: wgpu::FrontFace::Ccw,
           cull_mode: None,
@@ -389,18 +667,636 @@ pub fn construct_render_map_program(ctx: &mut Context, species_storage: &Storage
       },
   )?;
 
+  // The map wraps, so the tiled view just samples past the edges.
+  let nearest_sampler = construct_map_sampler(device, wgpu::FilterMode::Nearest)?;
+  let linear_sampler = construct_map_sampler(device, wgpu::FilterMode::Linear)?;
+
   let render_map_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      label: Some("Compute Map Bind Group"),
+      label: Some("Render Map Bind Group"),
       layout: &render_map_bind_group_layout,
       entries: &[
           wgpu::BindGroupEntry {
               binding: 0,
               resource: species_storage.buffer.as_entire_binding(),
           },
+          wgpu::BindGroupEntry {
+              binding: 1,
+              resource: camera_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 2,
+              resource: palette_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 3,
+              resource: wgpu::BindingResource::TextureView(&map_texture.view),
+          },
+          wgpu::BindGroupEntry {
+              binding: 4,
+              resource: wgpu::BindingResource::Sampler(&nearest_sampler),
+          },
+          wgpu::BindGroupEntry {
+              binding: 5,
+              resource: wgpu::BindingResource::Sampler(&linear_sampler),
+          },
       ],
   });
 
   let render_map_program = RenderProgram::new(render_map_pipeline, vec![render_map_bind_group])?;
 
   return Ok(render_map_program);
-} 
+}
+
+// Draws agents straight from the agent buffer, each instance reads agent
+// `instance_index * subsample` so no vertex buffer is bound.
+pub fn construct_render_agents_program(ctx: &mut Context, agent_storage: &Storage, species_storage: &Storage, camera_storage: &Storage, agent_style_storage: &Storage) -> GameResult<RenderProgram> {
+  let device = &ctx.gfx.wgpu().device;
+
+  let render_shader = util::construct_shader_module(device, "Render Agents Shader", include_str!("shaders/render_agents.wgsl"))?;
+  let render_agents_bind_group_entries = &[
+      wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(agent_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(species_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(camera_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(agent_style_storage.size as _),
+          },
+          count: None,
+      },
+  ];
+  let render_agents_bind_group_layout = util::construct_bind_group_layout(device, "Render Agents Bind Group Layout", render_agents_bind_group_entries)?;
+  let pipeline_layout = util::construct_pipeline_layout(device, "Render Agents Pipeline Layout", &vec![&render_agents_bind_group_layout], &vec![])?;
+  let render_agents_pipeline = util::construct_render_pipeline(
+      device, 
+      "Render Agents Pipeline", 
+      Some(&pipeline_layout), 
+      wgpu::VertexState {
+          module: &render_shader,
+          entry_point: "main_vs",
+          buffers: &[],
+      },
+      Some(wgpu::FragmentState {
+          module: &render_shader,
+          entry_point: "main_fs",
+          targets: &[wgpu::ColorTargetState {
+                  format: HDR_TEXTURE_FORMAT,
+                  blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                  write_mask: wgpu::ColorWrites::ALL,
+          }],
+      }),
+      None,
+      None,
+      wgpu::MultisampleState::default(),
+      wgpu::PrimitiveState {
+          topology: wgpu::PrimitiveTopology::TriangleList,
+          strip_index_format: None,
+          front_face: wgpu::FrontFace::Ccw,
+          cull_mode: None,
+          unclipped_depth: false,
+          polygon_mode: wgpu::PolygonMode::Fill,
+          conservative: false,
+      },
+  )?;
+
+  let render_agents_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Render Agents Bind Group"),
+      layout: &render_agents_bind_group_layout,
+      entries: &[
+          wgpu::BindGroupEntry {
+              binding: 0,
+              resource: agent_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 1,
+              resource: species_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 2,
+              resource: camera_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 3,
+              resource: agent_style_storage.buffer.as_entire_binding(),
+          },
+      ],
+  });
+
+  let render_agents_program = RenderProgram::new(render_agents_pipeline, vec![render_agents_bind_group])?;
+
+  return Ok(render_agents_program);
+}
+
+fn construct_map_sampler(device: &wgpu::Device, filter: wgpu::FilterMode) -> GameResult<wgpu::Sampler> {
+  let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Map Sampler"),
+      address_mode_u: wgpu::AddressMode::Repeat,
+      address_mode_v: wgpu::AddressMode::Repeat,
+      mag_filter: filter,
+      min_filter: filter,
+      ..Default::default()
+  });
+
+  return Ok(sampler);
+}
+
+// `entry_point` is `brush_map` for Paint and Erase or `brush_agents` for Spawn
+// and Push. Bind group `i` targets `map_storages[i]`, the current map at frames
+// of that parity.
+pub fn construct_brush_program(ctx: &mut Context, simulation_config: &SimulationConfig, constants_storage: &Storage, brush_storage: &Storage, agent_storage: &Storage, map_storages: &Vec<Storage>, entry_point: &str) -> GameResult<ComputeProgram> {
+  let device = &ctx.gfx.wgpu().device;
+
+  let brush_shader = util::construct_shader_module(device, "Brush Shader", include_str!("shaders/brush.wgsl"))?;
+
+  let brush_bind_group_entries = &[
+      wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(constants_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(brush_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(agent_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(map_storages[0].size as _),
+          },
+          count: None,
+      },
+  ];
+
+  let brush_bind_group_layout = util::construct_bind_group_layout(device, "Brush Bind Group Layout", brush_bind_group_entries)?;
+  let brush_pipeline_layout = util::construct_pipeline_layout(device, "Brush Pipeline Layout", &vec![&brush_bind_group_layout], &vec![])?;
+  let brush_pipeline = util::construct_compute_pipeline(device, "Brush Pipeline", Some(&brush_pipeline_layout), &brush_shader, entry_point)?;
+
+  let mut brush_bind_groups = Vec::new();
+
+  for i in 0..2 {
+      let brush_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some(&format!("Brush Bind Group {}", i)),
+          layout: &brush_bind_group_layout,
+          entries: &[
+              wgpu::BindGroupEntry {
+                  binding: 0,
+                  resource: constants_storage.buffer.as_entire_binding(),
+              },
+              wgpu::BindGroupEntry {
+                  binding: 1,
+                  resource: brush_storage.buffer.as_entire_binding(),
+              },
+              wgpu::BindGroupEntry {
+                  binding: 2,
+                  resource: agent_storage.buffer.as_entire_binding(),
+              },
+              wgpu::BindGroupEntry {
+                  binding: 3,
+                  resource: map_storages[i].buffer.as_entire_binding(),
+              },
+          ],
+      });
+
+      brush_bind_groups.push(brush_bind_group);
+  }
+
+  let invocations = if entry_point == "brush_agents" {
+      simulation_config.agent_count
+  } else {
+      (simulation_config.width * simulation_config.height) as u32
+  };
+  let work_group_count = (invocations as f32 / 32.0).ceil() as u32;
+  let brush_program = ComputeProgram::new(brush_pipeline, brush_bind_groups, (work_group_count, 1, 1))?;
+
+  return Ok(brush_program);
+}
+
+// Target of the map and agent passes, read by the post chain. `size` is the
+// window's drawable size.
+pub fn construct_hdr_texture(device: &wgpu::Device, size: [u32; 2]) -> GameResult<TextureStorage> {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("HDR Texture"),
+      size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: 1 },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: HDR_TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+  let texture_storage = TextureStorage { texture, view };
+
+  return Ok(texture_storage);
+}
+
+// Two half resolution textures the bloom is blurred back and forth between.
+pub fn construct_bloom_textures(device: &wgpu::Device, size: [u32; 2]) -> GameResult<Vec<TextureStorage>> {
+  let mut textures = Vec::new();
+
+  for i in 0..2 {
+      let texture = device.create_texture(&wgpu::TextureDescriptor {
+          label: Some(&format!("Bloom Texture {}", i)),
+          size: wgpu::Extent3d { width: (size[0] / 2).max(1), height: (size[1] / 2).max(1), depth_or_array_layers: 1 },
+          mip_level_count: 1,
+          sample_count: 1,
+          dimension: wgpu::TextureDimension::D2,
+          format: HDR_TEXTURE_FORMAT,
+          usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+      });
+      let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+      textures.push(TextureStorage { texture, view });
+  }
+
+  return Ok(textures);
+}
+
+pub fn construct_post_settings_storage(device: &wgpu::Device, post_settings: &PostSettings) -> GameResult<Storage> {
+  let size = mem::size_of::<PostSettings>();
+  let data = vec![*post_settings];
+  let buffer = util::construct_buffer_init(device, "Post Settings Buffer", &data, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+// The exposure histogram followed by the adapted luminance and the exposure,
+// all zero until the first frame is measured.
+pub fn construct_exposure_storage(device: &wgpu::Device) -> GameResult<Storage> {
+  let data = vec![0u32; HISTOGRAM_BINS + 2];
+  let size = mem::size_of::<u32>() * data.len();
+  let buffer = util::construct_buffer_init(device, "Exposure Buffer", &data, wgpu::BufferUsages::STORAGE)?;
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+// `entry_point` is `bright_pass`, `blur_horizontal` or `blur_vertical`, reading
+// `source` and writing `destination`. One invocation per destination texel.
+pub fn construct_bloom_program(ctx: &mut Context, post_settings_storage: &Storage, source: &TextureStorage, destination: &TextureStorage, size: [u32; 2], entry_point: &str) -> GameResult<ComputeProgram> {
+  let device = &ctx.gfx.wgpu().device;
+
+  let bloom_shader = util::construct_shader_module(device, "Bloom Shader", include_str!("shaders/bloom.wgsl"))?;
+
+  let bloom_bind_group_entries = &[
+      wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(post_settings_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::StorageTexture {
+              access: wgpu::StorageTextureAccess::WriteOnly,
+              format: HDR_TEXTURE_FORMAT,
+              view_dimension: wgpu::TextureViewDimension::D2,
+          },
+          count: None,
+      },
+  ];
+
+  let bloom_bind_group_layout = util::construct_bind_group_layout(device, "Bloom Bind Group Layout", bloom_bind_group_entries)?;
+  let bloom_pipeline_layout = util::construct_pipeline_layout(device, "Bloom Pipeline Layout", &vec![&bloom_bind_group_layout], &vec![])?;
+  let bloom_pipeline = util::construct_compute_pipeline(device, "Bloom Pipeline", Some(&bloom_pipeline_layout), &bloom_shader, entry_point)?;
+
+  let bloom_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Bloom Bind Group"),
+      layout: &bloom_bind_group_layout,
+      entries: &[
+          wgpu::BindGroupEntry {
+              binding: 0,
+              resource: post_settings_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::TextureView(&source.view),
+          },
+          wgpu::BindGroupEntry {
+              binding: 2,
+              resource: wgpu::BindingResource::TextureView(&destination.view),
+          },
+      ],
+  });
+
+  let bloom_program = ComputeProgram::new(bloom_pipeline, vec![bloom_bind_group], image_work_groups(size))?;
+
+  return Ok(bloom_program);
+}
+
+// `entry_point` is `histogram`, one invocation per pixel of `hdr_texture`, or
+// `average`, a single invocation.
+pub fn construct_exposure_program(ctx: &mut Context, post_settings_storage: &Storage, hdr_texture: &TextureStorage, exposure_storage: &Storage, size: [u32; 2], entry_point: &str) -> GameResult<ComputeProgram> {
+  let device = &ctx.gfx.wgpu().device;
+
+  let exposure_shader = util::construct_shader_module(device, "Exposure Shader", include_str!("shaders/exposure.wgsl"))?;
+
+  let exposure_bind_group_entries = &[
+      wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(post_settings_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(exposure_storage.size as _),
+          },
+          count: None,
+      },
+  ];
+
+  let exposure_bind_group_layout = util::construct_bind_group_layout(device, "Exposure Bind Group Layout", exposure_bind_group_entries)?;
+  let exposure_pipeline_layout = util::construct_pipeline_layout(device, "Exposure Pipeline Layout", &vec![&exposure_bind_group_layout], &vec![])?;
+  let exposure_pipeline = util::construct_compute_pipeline(device, "Exposure Pipeline", Some(&exposure_pipeline_layout), &exposure_shader, entry_point)?;
+
+  let exposure_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Exposure Bind Group"),
+      layout: &exposure_bind_group_layout,
+      entries: &[
+          wgpu::BindGroupEntry {
+              binding: 0,
+              resource: post_settings_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::TextureView(&hdr_texture.view),
+          },
+          wgpu::BindGroupEntry {
+              binding: 2,
+              resource: exposure_storage.buffer.as_entire_binding(),
+          },
+      ],
+  });
+
+  let dispatch_group = if entry_point == "histogram" { image_work_groups(size) } else { (1, 1, 1) };
+  let exposure_program = ComputeProgram::new(exposure_pipeline, vec![exposure_bind_group], dispatch_group)?;
+
+  return Ok(exposure_program);
+}
+
+// Draws `hdr_texture` with a full screen triangle, adding the bloom and applying
+// the exposure, tone map and vignette. `format` is the window's surface format,
+// or `CAPTURE_TEXTURE_FORMAT` for recording.
+pub fn construct_post_program(ctx: &mut Context, post_settings_storage: &Storage, hdr_texture: &TextureStorage, bloom_texture: &TextureStorage, exposure_storage: &Storage, format: wgpu::TextureFormat) -> GameResult<RenderProgram> {
+  let device = &ctx.gfx.wgpu().device;
+
+  let post_shader = util::construct_shader_module(device, "Post Shader", include_str!("shaders/post.wgsl"))?;
+  let post_bind_group_entries = &[
+      wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(post_settings_storage.size as _),
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+          },
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(exposure_storage.size as _),
+          },
+          count: None,
+      },
+  ];
+  let post_bind_group_layout = util::construct_bind_group_layout(device, "Post Bind Group Layout", post_bind_group_entries)?;
+  let pipeline_layout = util::construct_pipeline_layout(device, "Post Pipeline Layout", &vec![&post_bind_group_layout], &vec![])?;
+  let post_pipeline = util::construct_render_pipeline(
+      device, 
+      "Post Pipeline", 
+      Some(&pipeline_layout), 
+      wgpu::VertexState {
+          module: &post_shader,
+          entry_point: "main_vs",
+          buffers: &[],
+      },
+      Some(wgpu::FragmentState {
+          module: &post_shader,
+          entry_point: "main_fs",
+          targets: &[wgpu::ColorTargetState {
+                  format,
+                  blend: None,
+                  write_mask: wgpu::ColorWrites::ALL,
+          }],
+      }),
+      None,
+      None,
+      wgpu::MultisampleState::default(),
+      wgpu::PrimitiveState {
+          topology: wgpu::PrimitiveTopology::TriangleList,
+          strip_index_format: None,
+          front_face: wgpu::FrontFace::Ccw,
+          cull_mode: None,
+          unclipped_depth: false,
+          polygon_mode: wgpu::PolygonMode::Fill,
+          conservative: false,
+      },
+  )?;
+
+  let bloom_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Bloom Sampler"),
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      ..Default::default()
+  });
+
+  let post_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Post Bind Group"),
+      layout: &post_bind_group_layout,
+      entries: &[
+          wgpu::BindGroupEntry {
+              binding: 0,
+              resource: post_settings_storage.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::TextureView(&hdr_texture.view),
+          },
+          wgpu::BindGroupEntry {
+              binding: 2,
+              resource: wgpu::BindingResource::TextureView(&bloom_texture.view),
+          },
+          wgpu::BindGroupEntry {
+              binding: 3,
+              resource: wgpu::BindingResource::Sampler(&bloom_sampler),
+          },
+          wgpu::BindGroupEntry {
+              binding: 4,
+              resource: exposure_storage.buffer.as_entire_binding(),
+          },
+      ],
+  });
+
+  let post_program = RenderProgram::new(post_pipeline, vec![post_bind_group])?;
+
+  return Ok(post_program);
+}
+
+// Same size as the window, copied into `construct_capture_storage` each recorded frame.
+pub fn construct_capture_texture(device: &wgpu::Device, size: [u32; 2]) -> GameResult<TextureStorage> {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Capture Texture"),
+      size: wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: CAPTURE_TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+  let texture_storage = TextureStorage { texture, view };
+
+  return Ok(texture_storage);
+}
+
+// Rows of RGBA8 padded to `capture_bytes_per_row`.
+pub fn construct_capture_storage(device: &wgpu::Device, size: [u32; 2]) -> GameResult<Storage> {
+  let size = (capture_bytes_per_row(size[0]) * size[1]) as usize;
+  let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Capture Buffer"),
+      size: size as u64,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+  });
+
+  let storage = Storage { size, buffer };
+
+  return Ok(storage);
+}
+
+// Texture to buffer copies need rows aligned to 256 bytes.
+pub fn capture_bytes_per_row(width: u32) -> u32 {
+  let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+  return (width * 4 + alignment - 1) / alignment * alignment;
+}
+
+// 8x8 work groups covering an image, images can be too large for one row of
+// the 1D groups used for the map.
+fn image_work_groups(size: [u32; 2]) -> (u32, u32, u32) {
+  return ((size[0] as f32 / 8.0).ceil() as u32, (size[1] as f32 / 8.0).ceil() as u32, 1);
+}
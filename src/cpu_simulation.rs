@@ -0,0 +1,407 @@
+use std::f32::consts::{TAU, PI};
+use rayon::prelude::*;
+use ggez::{GameResult};
+use crate::{construct::*, SimulationConfig, Snapshot, Brush, BrushTool, brush_offset, brush_falloff, Agent, Trail, Species, SensorKernel, Boundary, DiffusionKernel, MAX_SPECIES, calculate_kernel, diffusion_weight, shader_seed};
+
+
+// Pure Rust mirror of `update_map.wgsl` and `update_agents.wgsl`, used to exercise
+// the simulation without a GPU and to compare against the compute shader output.
+pub struct CpuSimulation {
+    pub config: SimulationConfig,
+
+    pub species: Vec<Species>,
+    pub agents: Vec<Agent>,
+    pub trail_map: Vec<Trail>,
+    // Fixed point deposits waiting to be merged, see `DEPOSIT_SCALE`.
+    pub deposit: Vec<u32>,
+
+    pub frame: usize,
+}
+
+// Must match `DEPOSIT_SCALE` in the compute shaders.
+pub const DEPOSIT_SCALE: f32 = 65536.0;
+
+impl CpuSimulation {
+    pub fn new(config: SimulationConfig) -> GameResult<CpuSimulation> {
+        let mut species_count = 0;
+        let species   = construct_species(&mut species_count)?;
+        let agents    = construct_agents(&config)?;
+        let trail_map = construct_trail_map(&config)?;
+
+        return CpuSimulation::from_state(config, species, agents, trail_map);
+    }
+
+    pub fn from_state(config: SimulationConfig, species: Vec<Species>, agents: Vec<Agent>, trail_map: Vec<Trail>) -> GameResult<CpuSimulation> {
+        let deposit = vec![0; trail_map.len() * MAX_SPECIES];
+
+        let simulation = CpuSimulation {
+            config       ,
+            species      ,
+            agents       ,
+            trail_map    ,
+            deposit      ,
+            frame: 0     ,
+        };
+
+        return Ok(simulation);
+    }
+
+    pub fn from_snapshot(snapshot: Snapshot) -> GameResult<CpuSimulation> {
+        let Snapshot { config, frame, species, agents, maps, deposit } = snapshot;
+        let [map_0, map_1] = maps;
+        let trail_map = if frame % 2 == 0 { map_0 } else { map_1 };
+
+        let simulation = CpuSimulation { config, species, agents, trail_map, deposit, frame };
+
+        return Ok(simulation);
+    }
+
+    // The CPU keeps a single trail map, so it fills both buffers of the snapshot.
+    pub fn snapshot(&self) -> GameResult<Snapshot> {
+        let snapshot = Snapshot {
+            config: self.config.clone(),
+            frame: self.frame,
+            species: self.species.clone(),
+            agents: self.agents.clone(),
+            maps: [self.trail_map.clone(), self.trail_map.clone()],
+            deposit: self.deposit.clone(),
+        };
+
+        return Ok(snapshot);
+    }
+
+    // Mirror of `brush.wgsl`, applied to the current trail map and agents.
+    pub fn apply_brush(&mut self, brush: &Brush) -> GameResult {
+        let config = &self.config;
+        let aspect = config.width as f32 / config.height as f32;
+        let cell_size = [2.0 / config.width as f32, 2.0 / config.height as f32];
+
+        if !BrushTool::from_index(brush.tool).affects_agents() {
+            self.trail_map.par_iter_mut().for_each(|trail| {
+                let center = [trail.position[0] + cell_size[0] / 2.0, trail.position[1] + cell_size[1] / 2.0];
+                let offset = brush_offset(brush, center, aspect);
+                let amount = brush.strength * brush_falloff((offset[0] * offset[0] + offset[1] * offset[1]).sqrt(), brush.radius);
+
+                if amount <= 0.0 { return; }
+
+                if brush.tool == BrushTool::Paint.index() {
+                    trail.value[brush.species as usize % MAX_SPECIES] += amount;
+                } else {
+                    trail.value.iter_mut().for_each(|value| *value *= 1.0 - amount.min(1.0));
+                }
+            });
+
+            return Ok(());
+        }
+
+        // Agents pushed past the edge are brought back by the next agent step.
+        let agent_count = self.agents.len() as u32;
+
+        self.agents.par_iter_mut().enumerate().for_each(|(index, agent)| {
+            if brush.tool == BrushTool::Spawn.index() {
+                if (index as u32 + agent_count - brush.spawn_start % agent_count) % agent_count >= brush.spawn_count { return; }
+
+                let random = hash(hash(brush.seed.wrapping_add(index as u32)));
+                let distance = brush.radius * scale_to_range_01(random).sqrt();
+                let random = hash(random);
+                let theta = scale_to_range_01(random) * TAU;
+                let random = hash(random);
+
+                agent.position_x = brush.position[0] + theta.cos() * distance;
+                agent.position_y = brush.position[1] + theta.sin() * distance * aspect;
+                agent.angle = scale_to_range_01(random) * TAU;
+                agent.species = brush.species;
+
+                return;
+            }
+
+            let offset = brush_offset(brush, [agent.position_x, agent.position_y], aspect);
+            let distance = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+            let amount = brush.strength * brush_falloff(distance, brush.radius) * brush.radius;
+
+            if amount <= 0.0 || distance <= 0.0 { return; }
+
+            let direction = [offset[0] / distance, offset[1] / distance];
+
+            agent.position_x += direction[0] * amount;
+            agent.position_y += direction[1] * amount * aspect;
+            agent.angle = direction[1].atan2(direction[0]);
+        });
+
+        return Ok(());
+    }
+
+    pub fn reset(&mut self) -> GameResult {
+        self.agents    = construct_agents(&self.config)?;
+        self.trail_map = construct_trail_map(&self.config)?;
+        self.deposit   = vec![0; self.trail_map.len() * MAX_SPECIES];
+        self.frame     = 0;
+
+        return Ok(());
+    }
+
+    pub fn step(&mut self, delta_time: f32) -> GameResult {
+        self.trail_map = update_map(&self.trail_map, &mut self.deposit, &self.config)?;
+        update_agents(&mut self.agents, &self.trail_map, &mut self.deposit, &self.species, &self.config, delta_time, self.frame as u32)?;
+
+        self.frame += 1;
+
+        return Ok(());
+    }
+}
+
+pub fn hash(state: u32) -> u32 {
+    let mut res = state;
+
+    res ^= 2747636419;
+    res = res.wrapping_mul(2654435769);
+    res ^= res >> 16;
+    res = res.wrapping_mul(2654435769);
+    res ^= res >> 16;
+    res = res.wrapping_mul(2654435769);
+
+    return res;
+}
+
+pub fn scale_to_range_01(state: u32) -> f32 {
+    return state as f32 / 4294967295.0;
+}
+
+pub fn boundary_index(p: i32, size: i32, boundary: Boundary) -> Option<i32> {
+    return match boundary {
+        Boundary::Wrap => Some(p.rem_euclid(size)),
+        Boundary::Reflect => {
+            if p < 0 { Some((-p - 1).min(size - 1)) }
+            else if p >= size { Some((2 * size - p - 1).max(0)) }
+            else { Some(p) }
+        },
+        Boundary::Absorb => if p < 0 || p >= size { None } else { Some(p) },
+        Boundary::Clamp => Some(p.clamp(0, size - 1)),
+    };
+}
+
+// Returns `None` for cells outside the map when the boundary absorbs.
+pub fn who_cell(pos: (i32, i32), simulation_config: &SimulationConfig) -> Option<usize> {
+    let width = simulation_config.width;
+    let height = simulation_config.height;
+
+    let x = boundary_index(pos.0, width, simulation_config.boundary)?;
+    let y = boundary_index(pos.1, height, simulation_config.boundary)?;
+
+    return Some((y * width + x) as usize);
+}
+
+pub fn get_cell_index(position: [f32; 2], simulation_config: &SimulationConfig) -> Option<usize> {
+    let width = simulation_config.width as f32;
+    let height = simulation_config.height as f32;
+
+    let cell_x = ((position[0] + 1.0) / 2.0 * width).floor() as i32;
+    let cell_y = ((position[1] + 1.0) / 2.0 * height).floor() as i32;
+
+    return who_cell((cell_x, cell_y), simulation_config);
+}
+
+// Keeps an agent inside the map after it moves, see the boundary handling in
+// `update_agents.wgsl`.
+pub fn apply_boundary(agent: &mut Agent, random: u32, simulation_config: &SimulationConfig) {
+    let outside_x = agent.position_x >= 1.0 || agent.position_x < -1.0;
+    let outside_y = agent.position_y >= 1.0 || agent.position_y < -1.0;
+
+    match simulation_config.boundary {
+        Boundary::Wrap => {
+            if agent.position_x >= 1.0 { agent.position_x -= 2.0; }
+            else if agent.position_x < -1.0 { agent.position_x += 2.0; }
+
+            if agent.position_y >= 1.0 { agent.position_y -= 2.0; }
+            else if agent.position_y < -1.0 { agent.position_y += 2.0; }
+        },
+        Boundary::Reflect => {
+            if outside_x {
+                agent.position_x = (agent.position_x.signum() * 2.0 - agent.position_x).clamp(-1.0, 1.0);
+                agent.angle = PI - agent.angle;
+            }
+            if outside_y {
+                agent.position_y = (agent.position_y.signum() * 2.0 - agent.position_y).clamp(-1.0, 1.0);
+                agent.angle = -agent.angle;
+            }
+        },
+        Boundary::Absorb => {
+            if outside_x || outside_y {
+                let random = hash(random);
+                agent.position_x = scale_to_range_01(random) * 2.0 - 1.0;
+                let random = hash(random);
+                agent.position_y = scale_to_range_01(random) * 2.0 - 1.0;
+                let random = hash(random);
+                agent.angle = scale_to_range_01(random) * TAU;
+            }
+        },
+        Boundary::Clamp => {
+            agent.position_x = agent.position_x.clamp(-1.0, 1.0);
+            agent.position_y = agent.position_y.clamp(-1.0, 1.0);
+        },
+    }
+}
+
+pub fn quantize_deposit(weight: f32) -> u32 {
+    return (weight.max(0.0) * DEPOSIT_SCALE + 0.5).floor() as u32;
+}
+
+pub fn deposit_mass(deposit: &Vec<u32>) -> f32 {
+    return (deposit.iter().map(|&d| d as u64).sum::<u64>() as f64 / DEPOSIT_SCALE as f64) as f32;
+}
+
+// Weighted average of the cells along `offsets`, absorbed cells count as empty.
+fn blur_cell(values: &Vec<[f32; MAX_SPECIES]>, offsets: &Vec<((i32, i32), f32)>, pos: (i32, i32), simulation_config: &SimulationConfig) -> [f32; MAX_SPECIES] {
+    let mut sum = [0.0; MAX_SPECIES];
+    let mut total_weight = 0.0;
+
+    for &((x, y), weight) in offsets.iter() {
+        if let Some(index) = who_cell((pos.0 + x, pos.1 + y), simulation_config) {
+            for channel in 0..MAX_SPECIES {
+                sum[channel] += values[index][channel] * weight;
+            }
+        }
+        total_weight += weight;
+    }
+
+    sum.iter_mut().for_each(|value| *value /= total_weight);
+
+    return sum;
+}
+
+// Blurs `map_src` with the configured kernel, see `main` and `blur_horizontal` in `update_map.wgsl`.
+pub fn blur_map(map_src: &Vec<Trail>, simulation_config: &SimulationConfig) -> Vec<[f32; MAX_SPECIES]> {
+    let width = simulation_config.width;
+    let kernel = simulation_config.diffusion_kernel;
+    let radius = kernel.radius() as i32;
+    let values: Vec<[f32; MAX_SPECIES]> = map_src.iter().map(|trail| trail.value).collect();
+    let position = |index: usize| (index as i32 % width, index as i32 / width);
+
+    if let DiffusionKernel::SeparableGaussian(_) = kernel {
+        let horizontal = (-radius..=radius).map(|x| ((x, 0), diffusion_weight(kernel, x))).collect();
+        let vertical = (-radius..=radius).map(|y| ((0, y), diffusion_weight(kernel, y))).collect();
+
+        let rows = (0..values.len()).into_par_iter().map(|index| blur_cell(&values, &horizontal, position(index), simulation_config)).collect();
+
+        return (0..values.len()).into_par_iter().map(|index| blur_cell(&rows, &vertical, position(index), simulation_config)).collect();
+    }
+
+    let offsets = (-radius..=radius)
+        .flat_map(|y| (-radius..=radius).map(move |x| ((x, y), diffusion_weight(kernel, x) * diffusion_weight(kernel, y))))
+        .collect();
+
+    return (0..values.len()).into_par_iter().map(|index| blur_cell(&values, &offsets, position(index), simulation_config)).collect();
+}
+
+// Diffuses and decays `map_src`, then merges and clears the pending deposits.
+pub fn update_map(map_src: &Vec<Trail>, deposit: &mut Vec<u32>, simulation_config: &SimulationConfig) -> GameResult<Vec<Trail>> {
+    let diffusion_rate = simulation_config.diffusion_rate.clamp(0.0, 1.0);
+    let decay_rate = simulation_config.decay_rate.clamp(0.0, 1.0);
+
+    let blurred = blur_map(map_src, simulation_config);
+
+    let map_dst = map_src.par_iter().enumerate().map(|(index, trail)| {
+        let mut trail = *trail;
+
+        for channel in 0..MAX_SPECIES {
+            let original = trail.value[channel];
+            trail.value[channel] = (original + (blurred[index][channel] - original) * diffusion_rate) * (1.0 - decay_rate);
+        }
+
+        for channel in 0..MAX_SPECIES {
+            trail.value[channel] += deposit[index * MAX_SPECIES + channel] as f32 / DEPOSIT_SCALE;
+        }
+
+        let mut strongest = 0.0;
+
+        for channel in 0..MAX_SPECIES {
+            if trail.value[channel] > strongest {
+                strongest = trail.value[channel];
+                trail.species = channel as u32;
+            }
+        }
+
+        return trail;
+    }).collect();
+
+    deposit.iter_mut().for_each(|d| *d = 0);
+
+    return Ok(map_dst);
+}
+
+// Distances are measured in units of the map width, see `aspect_scale` in `update_agents.wgsl`.
+pub fn aspect_scale(simulation_config: &SimulationConfig) -> [f32; 2] {
+    return [1.0, simulation_config.width as f32 / simulation_config.height as f32];
+}
+
+pub fn sense(agent: &Agent, species: &Species, kernel: &Vec<((i32, i32), f32)>, map: &Vec<Trail>, simulation_config: &SimulationConfig, sensor_angle_offset: f32) -> f32 {
+    let scale = aspect_scale(simulation_config);
+    let sensor_angle = agent.angle + sensor_angle_offset;
+    let sensor_position = [
+        agent.position_x + sensor_angle.cos() * scale[0] * species.sensor_distance,
+        agent.position_y + sensor_angle.sin() * scale[1] * species.sensor_distance,
+    ];
+
+    let cell_size = [2.0 / simulation_config.width as f32, 2.0 / simulation_config.height as f32];
+
+    return kernel.iter().map(|&((x, y), weight)| {
+        let position = [sensor_position[0] + x as f32 * cell_size[0], sensor_position[1] + y as f32 * cell_size[1]];
+        let sample = match get_cell_index(position, simulation_config) {
+            Some(index) => map[index],
+            None => return 0.0,
+        };
+        let value: f32 = sample.value.iter().zip(species.interaction.iter()).map(|(value, interaction)| value * interaction).sum();
+
+        return value * weight;
+    }).sum();
+}
+
+pub fn update_agents(agents: &mut Vec<Agent>, map: &Vec<Trail>, deposit: &mut Vec<u32>, species: &Vec<Species>, simulation_config: &SimulationConfig, delta_time: f32, frame: u32) -> GameResult {
+    let scale = aspect_scale(simulation_config);
+    let seed = shader_seed(simulation_config.seed);
+
+    let kernels: Vec<Vec<((i32, i32), f32)>> = species.iter()
+        .map(|species| calculate_kernel(SensorKernel::from_index(species.sensor_kernel), species.sensor_size))
+        .collect();
+
+    // Steering only reads the map, so it runs in parallel. Deposits are summed
+    // afterwards, saturating like the atomic adds on the GPU.
+    agents.par_iter_mut().enumerate().for_each(|(index, agent)| {
+        let random = hash(hash(seed.wrapping_add(index as u32)).wrapping_add(frame));
+
+        let kernel = &kernels[agent.species as usize];
+        let species = &species[agent.species as usize];
+
+        let sensor_angle_rad = species.sensor_angle * (PI / 180.0);
+        let weight_forward = sense(agent, species, kernel, map, simulation_config, 0.0);
+        let weight_left    = sense(agent, species, kernel, map, simulation_config, sensor_angle_rad);
+        let weight_right   = sense(agent, species, kernel, map, simulation_config, -sensor_angle_rad);
+
+        let mod_turn_speed = species.turn_speed * TAU;
+        let random_steer_strength = scale_to_range_01(random);
+
+        if weight_forward < weight_left && weight_forward < weight_right {
+            agent.angle += (random_steer_strength + species.forward_bias) * mod_turn_speed * delta_time;
+        }
+        else if weight_right > weight_left {
+            agent.angle -= (random_steer_strength + species.left_bias) * mod_turn_speed * delta_time;
+        }
+        else if weight_left > weight_right {
+            agent.angle += (random_steer_strength + species.right_bias) * mod_turn_speed * delta_time;
+        }
+
+        agent.position_x += agent.angle.cos() * scale[0] * delta_time * species.move_speed;
+        agent.position_y += agent.angle.sin() * scale[1] * delta_time * species.move_speed;
+
+        apply_boundary(agent, random, simulation_config);
+    });
+
+    for agent in agents.iter() {
+        if let Some(map_index) = get_cell_index([agent.position_x, agent.position_y], simulation_config) {
+            let d = &mut deposit[map_index * MAX_SPECIES + agent.species as usize];
+            *d = d.saturating_add(quantize_deposit(species[agent.species as usize].weight));
+        }
+    }
+
+    return Ok(());
+}
@@ -1,35 +1,304 @@
-use ggez::{GameResult, Context, event::EventHandler, graphics::{self, TextFragment, Color, Text, Canvas, DrawParam}, mint::Point2, input::keyboard::{KeyCode}};
+use std::{path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use ggez::{GameResult, Context, event::EventHandler, graphics::{self, TextFragment, Color, Text, Canvas, DrawParam}, mint::Point2, input::{keyboard::KeyCode, mouse::MouseButton}};
 use ggez_egui::{EguiBackend, egui};
-use crate::{load, SimulationConfig, WindowConfig, Simulation};
+use crate::{load, load_species_configs, load_render_config, construct_species_from, config_root, validate_all, save_all, show_panel, show_brush_panel, show_render_panel, window_to_map, PanelActions, Brush, BrushSettings, RecordOptions, RecordFormat, Recorder, Provenance, save_screenshot, Camera, Palette, AgentStyle, PostSettings, SimulationConfig, SpeciesConfig, WindowConfig, RenderConfig, Simulation, ConfigWatcher};
 
 
+// Written by F5 and read back by F9.
+pub const SNAPSHOT_PATH: &str = "snapshots/quicksave.snapshot";
+
+// F10 records a PNG sequence into a new directory here unless `--record` was passed.
+pub const RECORDINGS_DIR: &str = "recordings";
+
+// F12 saves the frame here with the configs that made it, see `screenshot.rs`.
+pub const SCREENSHOTS_DIR: &str = "screenshots";
+
+// How often the config directory is checked for edits.
+pub const CONFIG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct Engine {
     simulation: Simulation,
     window_config: WindowConfig,
+    render_config: RenderConfig,
     egui_backend: EguiBackend,
+    config_watcher: ConfigWatcher,
+    // Shown on screen while the edited configs fail to load.
+    config_message: Option<String>,
+    // Edited by the parameter panel, `agent_count` only reaches the simulation
+    // once its slider is released.
+    panel_config: SimulationConfig,
+    species_configs: Vec<SpeciesConfig>,
+    show_panel: bool,
+    // Single steps requested while paused, run in the next `draw`.
+    pending_steps: usize,
+    brush_settings: BrushSettings,
+    // This frame's brush stroke, applied in the next `draw`.
+    pending_brush: Option<Brush>,
+    // From `--record`, used by every recording F10 starts.
+    record_options: Option<RecordOptions>,
+    recorder: Option<Recorder>,
+    // Whether the frame captured in the last `draw` goes to the recorder.
+    pending_recording: bool,
+    screenshot_requested: bool,
+    // Configs of the frame captured for a screenshot in the last `draw`.
+    pending_screenshot: Option<Provenance>,
     running: bool,
     paused: bool,
 }
 
 impl Engine {
-    pub fn new(ctx: &mut Context) -> GameResult<Engine> {
+    // With `record_options` the recording starts as soon as the simulation runs.
+    pub fn new(ctx: &mut Context, record_options: Option<RecordOptions>) -> GameResult<Engine> {
         let simulation_config = load::<SimulationConfig>("simulation")?;
         let window_config = load::<WindowConfig>("window")?;
+        let render_config = load_render_config(&config_root())?;
+        let species_configs = load_species_configs()?;
         let egui_backend = EguiBackend::default();
-        let simulation = Simulation::new(ctx, simulation_config, window_config.clone())?;
+        let species = construct_species_from(&species_configs, &mut 0)?;
+        let panel_config = simulation_config.clone();
+        let mut simulation = Simulation::with_species(ctx, simulation_config, species, window_config.clone())?;
+        simulation.set_palette(ctx, Palette::new(&render_config)?)?;
         let running = window_config.auto_run;
         let paused = false;
+        let config_watcher = ConfigWatcher::new(config_root(), CONFIG_POLL_INTERVAL)?;
+        let config_message = None;
+        let show_panel = true;
+        let pending_steps = 0;
+        let brush_settings = BrushSettings::new()?;
+        let pending_brush = None;
+        let recorder = match &record_options {
+            Some(options) => Some(Recorder::new(options.clone())?),
+            None => None,
+        };
+        let pending_recording = false;
+        let screenshot_requested = false;
+        let pending_screenshot = None;
 
         ctx.gfx.add_font(
             "Main",
             graphics::FontData::from_path(ctx, "/fonts/BN6FontBold.ttf")?,
         );
 
-        let engine = Engine { simulation, window_config, egui_backend, running, paused };
+        let engine = Engine { simulation, window_config, render_config, egui_backend, config_watcher, config_message, panel_config, species_configs, show_panel, pending_steps, brush_settings, pending_brush, record_options, recorder, pending_recording, screenshot_requested, pending_screenshot, running, paused };
 
         return Ok(engine);
     }
 
+    // Reapplies the configs after an edit. Invalid configs leave the running
+    // simulation untouched and report why on screen.
+    fn reload_config(&mut self, ctx: &mut Context) -> GameResult {
+        let errors = validate_all(&self.config_watcher.root);
+
+        if !errors.is_empty() {
+            let lines: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+            self.config_message = Some(lines.join("\r"));
+
+            return Ok(());
+        }
+
+        let reloaded = load::<SimulationConfig>("simulation").and_then(|simulation_config| {
+            let species_configs = load_species_configs()?;
+            let window_config = load::<WindowConfig>("window")?;
+            let render_config = load_render_config(&self.config_watcher.root)?;
+            let palette = Palette::new(&render_config)?;
+
+            return Ok((simulation_config, species_configs, window_config, render_config, palette));
+        });
+
+        let (simulation_config, species_configs, window_config, render_config, palette) = match reloaded {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                self.config_message = Some(e.to_string());
+                return Ok(());
+            },
+        };
+
+        // Rebuilding for a new size or pattern can fail too, the old run and
+        // configs stay as they were when it does.
+        let applied = construct_species_from(&species_configs, &mut 0)
+            .and_then(|species| self.simulation.apply_config(ctx, simulation_config.clone(), species))
+            .and_then(|_| self.simulation.set_palette(ctx, palette));
+
+        if let Err(e) = applied {
+            self.config_message = Some(e.to_string());
+            return Ok(());
+        }
+
+        self.panel_config = simulation_config;
+        self.species_configs = species_configs;
+        self.window_config = window_config;
+        self.render_config = render_config;
+        self.config_message = None;
+
+        return Ok(());
+    }
+
+    // Uploads the panel's configs. Without `rebuild` the running `agent_count` is
+    // kept so a half dragged slider doesn't reallocate every frame.
+    fn apply_panel_config(&mut self, ctx: &mut Context, rebuild: bool) -> GameResult {
+        let mut simulation_config = self.panel_config.clone();
+        if !rebuild { simulation_config.agent_count = self.simulation.config.agent_count; }
+
+        let species = construct_species_from(&self.species_configs, &mut 0)?;
+
+        return self.simulation.apply_config(ctx, simulation_config, species);
+    }
+
+    fn update_panel(&mut self, ctx: &mut Context) -> GameResult {
+        let mut actions = PanelActions::default();
+        let mut render_changed = false;
+
+        if self.show_panel {
+            let egui_ctx = self.egui_backend.ctx();
+            let panel_config = &mut self.panel_config;
+            let species_configs = &mut self.species_configs;
+            let brush_settings = &mut self.brush_settings;
+            let render_config = &mut self.render_config;
+            let paused = self.paused;
+
+            egui::Window::new("Parameters").show(&egui_ctx, |ui| {
+                actions = show_panel(ui, panel_config, species_configs, paused);
+                render_changed = show_render_panel(ui, render_config);
+                show_brush_panel(ui, brush_settings, species_configs.len());
+            });
+        }
+        self.egui_backend.update(ctx);
+
+        if actions.rebuild || actions.changed { self.apply_panel_config(ctx, actions.rebuild)?; }
+        if render_changed { self.simulation.set_palette(ctx, Palette::new(&self.render_config)?)?; }
+
+        if actions.toggle_pause {
+            self.running = true;
+            self.paused = !self.paused;
+        }
+        if actions.step { self.pending_steps += 1; }
+        if actions.reset { self.simulation.reset(ctx)?; }
+
+        if actions.save {
+            match save_all(&self.config_watcher.root, &self.panel_config, &self.window_config, &self.render_config, &self.species_configs) {
+                Ok(dir) => {
+                    eprintln!("Saved config to {}", dir.display());
+                    // Saving from the bundled defaults creates a user config directory.
+                    self.config_watcher = ConfigWatcher::new(config_root(), CONFIG_POLL_INTERVAL)?;
+                },
+                Err(e) => eprintln!("Failed to save config: {}", e),
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Keeps the camera's viewport, the agent sprite size and the post chain in
+    // step with the window, the map and the render config.
+    fn update_view(&mut self, ctx: &mut Context) -> GameResult {
+        let (width, height) = ctx.gfx.drawable_size();
+
+        // A minimised window has no size to draw at.
+        if width < 1.0 || height < 1.0 { return Ok(()); }
+
+        let size = [width as u32, height as u32];
+        if size != self.simulation.post_chain.size { self.simulation.resize(ctx, size)?; }
+
+        // Written every frame since the exposure adapts over the frame time.
+        let post_settings = PostSettings::new(&self.render_config.post, ctx.time.delta().as_secs_f32())?;
+        self.simulation.set_post_settings(ctx, post_settings)?;
+        let map_size = [self.simulation.config.width as f32, self.simulation.config.height as f32];
+
+        let mut camera = self.simulation.camera;
+        camera.fit([width, height], map_size, self.render_config.keep_aspect);
+        if camera != self.simulation.camera { self.simulation.set_camera(ctx, camera)?; }
+
+        let agent_style = AgentStyle::new(&self.render_config, [width, height])?;
+        if agent_style != self.simulation.agent_style { self.simulation.set_agent_style(ctx, agent_style)?; }
+
+        return Ok(());
+    }
+
+    fn start_recording(&mut self) -> GameResult {
+        let options = match &self.record_options {
+            Some(options) => options.clone(),
+            None => {
+                let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+                RecordOptions { format: RecordFormat::PngSequence, output: PathBuf::from(RECORDINGS_DIR).join(seconds.to_string()), every: 1, frames: None, size: None, fps: 30 }
+            },
+        };
+
+        eprintln!("Recording to {}", options.output.display());
+        self.recorder = Some(Recorder::new(options)?);
+
+        return Ok(());
+    }
+
+    fn stop_recording(&mut self) -> GameResult {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.finish()?;
+            eprintln!("Recorded {} frames to {}", recorder.frames_written, recorder.options.output.display());
+        }
+
+        return Ok(());
+    }
+
+    // Hands the frame captured in the last `draw` to the recorder and writes the
+    // screenshot asked for.
+    fn update_capture(&mut self, ctx: &mut Context) -> GameResult {
+        let (frame, size) = match self.simulation.read_capture(ctx)? {
+            Some(capture) => capture,
+            None => return Ok(()),
+        };
+
+        if let Some(provenance) = self.pending_screenshot.take() {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+            let path = PathBuf::from(SCREENSHOTS_DIR).join(format!("physarum_{}_{}.png", provenance.frame, seconds));
+
+            match save_screenshot(&path, &frame, size, &provenance) {
+                Ok(()) => eprintln!("Saved screenshot to {}", path.display()),
+                Err(e) => eprintln!("Failed to save screenshot: {}", e),
+            }
+        }
+
+        if !self.pending_recording { return Ok(()); }
+        self.pending_recording = false;
+
+        let done = match &mut self.recorder {
+            Some(recorder) => recorder.push_frame(&frame, size[0], size[1])?,
+            None => false,
+        };
+        if done { self.stop_recording()?; }
+
+        return Ok(());
+    }
+
+    // Turns a held left mouse button into this frame's brush stroke, unless the
+    // pointer is over the panel.
+    fn update_brush(&mut self, ctx: &mut Context) -> GameResult {
+        if !self.running || !ctx.mouse.button_pressed(MouseButton::Left) { return Ok(()); }
+        if self.egui_backend.ctx().wants_pointer_input() { return Ok(()); }
+
+        let mouse = ctx.mouse.position();
+        let (width, height) = ctx.gfx.drawable_size();
+        let position = self.simulation.camera.view_to_map(window_to_map([mouse.x, mouse.y], [width, height]));
+
+        self.pending_brush = Some(self.brush_settings.stroke(position, self.simulation.config.agent_count)?);
+
+        return Ok(());
+    }
+
+    fn render_config_message(&mut self, canvas: &mut Canvas) -> GameResult {
+        let message = match &self.config_message {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+
+        let text_fragment = TextFragment::new(format!("Config not reloaded:\r{}", message))
+            .font("Main")
+            .color(Color::new(1.0, 0.2, 0.2, 1.0));
+        let text = Text::new(text_fragment);
+        let text_draw_param = DrawParam::new().dest(Point2 { x: 0.0, y: 32.0 });
+        canvas.draw(&text, text_draw_param);
+
+        return Ok(());
+    }
+
     fn render_intro_text(&mut self, canvas: &mut Canvas) -> GameResult {
         let text_fragment = TextFragment::new("Press SPACE to Start\rPress P to Pause\rPress R to Restart\rPress ESC to close")
             .font("Main")
@@ -55,16 +324,59 @@ impl Engine {
 
 impl EventHandler for Engine {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.update_capture(ctx)?;
+
         if ctx.keyboard.is_key_just_pressed(KeyCode::Space) { self.running = true; }
         if self.running && ctx.keyboard.is_key_just_pressed(KeyCode::P) { self.paused = !self.paused; }
+
+        if ctx.keyboard.is_key_just_pressed(KeyCode::F5) {
+            match self.simulation.save_snapshot(ctx, Path::new(SNAPSHOT_PATH)) {
+                Ok(()) => eprintln!("Saved snapshot to {}", SNAPSHOT_PATH),
+                Err(e) => eprintln!("Failed to save snapshot: {}", e),
+            }
+        }
+        if ctx.keyboard.is_key_just_pressed(KeyCode::F9) {
+            match self.simulation.load_snapshot(ctx, Path::new(SNAPSHOT_PATH)) {
+                Ok(()) => {
+                    // The panel edits and saves the snapshot's species from now on.
+                    let species_count = self.simulation.species.len();
+                    self.species_configs = self.simulation.species.iter().map(|species| species.to_config(species_count)).collect::<GameResult<_>>()?;
+                    self.panel_config = self.simulation.config.clone();
+                    self.running = true;
+                },
+                Err(e) => eprintln!("Failed to load snapshot: {}", e),
+            }
+        }
         
-        if !self.running || self.paused { return Ok(()); }
+        if ctx.keyboard.is_key_just_pressed(KeyCode::F10) {
+            if self.recorder.is_some() { self.stop_recording()?; } else { self.start_recording()?; }
+        }
+
+        if ctx.keyboard.is_key_just_pressed(KeyCode::F12) {
+            // Only a running simulation draws the frame a screenshot captures.
+            if self.running { self.screenshot_requested = true; } else { eprintln!("Start the simulation before taking a screenshot"); }
+        }
+
+        if ctx.keyboard.is_key_just_pressed(KeyCode::Tab) { self.show_panel = !self.show_panel; }
+
+        if ctx.keyboard.is_key_just_pressed(KeyCode::T) {
+            let mut camera = self.simulation.camera;
+            camera.toggle_tiled();
+            self.simulation.set_camera(ctx, camera)?;
+        }
+        if ctx.keyboard.is_key_just_pressed(KeyCode::V) { self.render_config.mode = self.render_config.mode.next(); }
+        if ctx.keyboard.is_key_just_pressed(KeyCode::Home) {
+            let camera = Camera { tiled: self.simulation.camera.tiled, ..Camera::new()? };
+            self.simulation.set_camera(ctx, camera)?;
+        }
+
+        if self.config_watcher.poll() { self.reload_config(ctx)?; }
+
+        self.update_panel(ctx)?;
+        self.update_view(ctx)?;
+        self.update_brush(ctx)?;
 
-        // let egui_ctx = self.egui_backend.ctx();
-        // egui::Window::new("config").show(&egui_ctx, |ui| {
-        //     ui.label("testing");
-        // });
-        // self.egui_backend.update(ctx);
+        if !self.running || self.paused { return Ok(()); }
 
         if ctx.keyboard.is_key_just_pressed(KeyCode::R) {
             self.simulation.reset(ctx)?;
@@ -74,24 +386,71 @@ impl EventHandler for Engine {
         return Ok(());
     }
 
+    // Zooms towards the mouse.
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> GameResult {
+        if self.egui_backend.ctx().wants_pointer_input() { return Ok(()); }
+
+        let mouse = ctx.mouse.position();
+        let (width, height) = ctx.gfx.drawable_size();
+        let mut camera = self.simulation.camera;
+        camera.zoom_at(window_to_map([mouse.x, mouse.y], [width, height]), 1.1f32.powf(y));
+
+        return self.simulation.set_camera(ctx, camera);
+    }
+
+    // Dragging with the right mouse button pans, the left one is the brush.
+    fn mouse_motion_event(&mut self, ctx: &mut Context, _x: f32, _y: f32, dx: f32, dy: f32) -> GameResult {
+        if !ctx.mouse.button_pressed(MouseButton::Right) || self.egui_backend.ctx().wants_pointer_input() { return Ok(()); }
+
+        let (width, height) = ctx.gfx.drawable_size();
+        let mut camera = self.simulation.camera;
+        camera.pan([dx / width * 2.0, -dy / height * 2.0]);
+
+        return self.simulation.set_camera(ctx, camera);
+    }
+
+    // Writes out a recording still in progress.
+    fn quit_event(&mut self, _ctx: &mut Context) -> GameResult<bool> {
+        self.stop_recording()?;
+
+        return Ok(false);
+    }
+
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         if self.running {
-            if self.paused { return Ok(()); }
+            if let Some(brush) = self.pending_brush.take() { self.simulation.apply_brush(ctx, brush)?; }
+            if !self.paused { self.simulation.advance(ctx)?; }
+            if self.pending_steps > 0 {
+                self.simulation.step(ctx, self.pending_steps)?;
+                self.pending_steps = 0;
+            }
+
+            // Recordings and screenshots leave out the UI drawn below.
+            if self.screenshot_requested {
+                self.screenshot_requested = false;
 
-            self.simulation.render(ctx)?;
+                // Taken from what is on the GPU, which is what drew the frame.
+                let species_count = self.simulation.species.len();
+                let species_configs: Vec<SpeciesConfig> = self.simulation.species.iter().map(|species| species.to_config(species_count)).collect::<GameResult<_>>()?;
+                self.pending_screenshot = Some(Provenance::new(&self.simulation.config, &species_configs, &self.render_config, self.simulation.frame)?);
+            }
+            self.pending_recording = self.recorder.as_mut().is_some_and(|recorder| !recorder.skip());
+            self.simulation.set_capturing(ctx, self.recorder.is_some() || self.pending_screenshot.is_some())?;
+            self.simulation.render(ctx, self.render_config.mode, self.pending_recording || self.pending_screenshot.is_some())?;
 
-            if !self.window_config.show_fps { return Ok(()); }
-            
             let mut canvas = Canvas::from_frame(ctx, None);
             canvas.draw(&self.egui_backend, DrawParam::default());
-            self.render_fps(ctx, &mut canvas)?;
+            if self.window_config.show_fps { self.render_fps(ctx, &mut canvas)?; }
+            self.render_config_message(&mut canvas)?;
             canvas.finish(ctx)?;
 
             return Ok(());
         }
 
         let mut canvas = Canvas::from_frame(ctx, self.window_config.background); 
+        canvas.draw(&self.egui_backend, DrawParam::default());
         self.render_intro_text(&mut canvas)?;
+        self.render_config_message(&mut canvas)?;
         canvas.finish(ctx)?;
 
         return Ok(());
@@ -1,9 +1,257 @@
 use serde::{Serialize, Deserialize};
 
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 pub enum Pattern {
+    #[default]
     Random,
     Center,
     Spherical,
+    Ring,
+    InwardCircle,
+    Clusters,
+    // Path to a PNG in the config directory, agents spawn on its bright pixels.
+    Image(String),
+}
+
+impl Pattern {
+    pub fn default_heading(&self) -> Heading {
+        return match self {
+            Pattern::Spherical => Heading::Outward,
+            Pattern::InwardCircle => Heading::Inward,
+            Pattern::Ring => Heading::Inward,
+            _ => Heading::Random,
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Heading {
+    Random,
+    Inward,
+    Outward,
+    Tangent,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum SensorKernel {
+    #[default]
+    Square,
+    Disc,
+    Gaussian,
+}
+
+impl SensorKernel {
+    pub fn from_index(index: u32) -> SensorKernel {
+        return match index {
+            1 => SensorKernel::Disc,
+            2 => SensorKernel::Gaussian,
+            _ => SensorKernel::Square,
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum Boundary {
+    #[default]
+    Wrap,
+    Reflect,
+    Absorb,
+    Clamp,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum DiffusionKernel {
+    #[default]
+    Box3,
+    Gaussian5,
+    // Blurred in a horizontal then a vertical pass, the value is the radius in cells.
+    SeparableGaussian(u32),
+}
+
+impl DiffusionKernel {
+    pub fn index(&self) -> u32 {
+        return match self {
+            DiffusionKernel::Box3 => 0,
+            DiffusionKernel::Gaussian5 => 1,
+            DiffusionKernel::SeparableGaussian(_) => 2,
+        };
+    }
+
+    pub fn radius(&self) -> u32 {
+        return match self {
+            DiffusionKernel::Box3 => 1,
+            DiffusionKernel::Gaussian5 => 2,
+            DiffusionKernel::SeparableGaussian(radius) => *radius,
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum BrushTool {
+    // Adds trail of the selected species.
+    #[default]
+    Paint,
+    Erase,
+    // Moves agents onto the brush as the selected species.
+    Spawn,
+    // Pushes agents out of the brush.
+    Push,
+}
+
+impl BrushTool {
+    pub fn index(&self) -> u32 {
+        return match self {
+            BrushTool::Paint => 0,
+            BrushTool::Erase => 1,
+            BrushTool::Spawn => 2,
+            BrushTool::Push => 3,
+        };
+    }
+
+    pub fn from_index(index: u32) -> BrushTool {
+        return match index {
+            1 => BrushTool::Erase,
+            2 => BrushTool::Spawn,
+            3 => BrushTool::Push,
+            _ => BrushTool::Paint,
+        };
+    }
+
+    // Paint and Erase change the trail map, Spawn and Push move agents.
+    pub fn affects_agents(&self) -> bool {
+        return matches!(self, BrushTool::Spawn | BrushTool::Push);
+    }
+}
+
+// How `render_map.wgsl` turns trail values into colours.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum ColorMap {
+    // Each species in its own colour, combined by `SpeciesBlend`.
+    #[default]
+    Species,
+    Viridis,
+    Magma,
+    // `(position, "#rrggbb")` stops with positions rising from 0 to 1, sampled by
+    // the total trail value.
+    Gradient(Vec<(f32, String)>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum SpeciesBlend {
+    // Sums every species' colour weighted by its trail.
+    #[default]
+    Additive,
+    // Takes the brightest weighted colour per channel.
+    Max,
+    // Only the species with the most trail in the cell shows.
+    Dominant,
+}
+
+impl SpeciesBlend {
+    pub fn index(&self) -> u32 {
+        return match self {
+            SpeciesBlend::Additive => 0,
+            SpeciesBlend::Max => 1,
+            SpeciesBlend::Dominant => 2,
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum TextureFilter {
+    // Sharp cells, best when zoomed in.
+    #[default]
+    Nearest,
+    Linear,
+}
+
+impl TextureFilter {
+    pub fn index(&self) -> u32 {
+        return match self {
+            TextureFilter::Nearest => 0,
+            TextureFilter::Linear => 1,
+        };
+    }
+}
+
+// Which layers the window shows.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum RenderMode {
+    #[default]
+    Trail,
+    Agents,
+    // Agents drawn over the trail.
+    Both,
+}
+
+impl RenderMode {
+    pub fn next(&self) -> RenderMode {
+        return match self {
+            RenderMode::Trail => RenderMode::Agents,
+            RenderMode::Agents => RenderMode::Both,
+            RenderMode::Both => RenderMode::Trail,
+        };
+    }
+
+    pub fn shows_trail(&self) -> bool {
+        return *self != RenderMode::Agents;
+    }
+
+    pub fn shows_agents(&self) -> bool {
+        return *self != RenderMode::Trail;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum AgentShape {
+    // A square dot.
+    #[default]
+    Point,
+    // A triangle pointing along the agent's heading.
+    Sprite,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum AgentColoring {
+    #[default]
+    Species,
+    // Hue follows the heading angle.
+    Heading,
+}
+
+// Curve that maps exposed HDR colours into the displayable range.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum ToneMap {
+    // Clips at 1, the look from before the post chain.
+    #[default]
+    None,
+    Reinhard,
+    // Narkowicz's fit of the ACES reference curve.
+    Aces,
+    // Hable's Uncharted 2 curve.
+    Filmic,
+}
+
+impl ToneMap {
+    pub fn index(&self) -> u32 {
+        return match self {
+            ToneMap::None => 0,
+            ToneMap::Reinhard => 1,
+            ToneMap::Aces => 2,
+            ToneMap::Filmic => 3,
+        };
+    }
+}
+
+// What `--record` writes.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum RecordFormat {
+    // Numbered `frame_<n>.png` files in a directory.
+    #[default]
+    PngSequence,
+    // One animated PNG, kept in memory until the recording stops.
+    Apng,
+    // Bare RGBA8 frames one after another, for piping into an encoder.
+    Raw,
 }
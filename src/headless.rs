@@ -0,0 +1,133 @@
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::{Path, PathBuf}};
+use ggez::{GameResult, GameError};
+use crate::{load, load_render_config, config_root, shade, post_process, write_png, SimulationConfig, CpuSimulation, Trail, Species, Palette, PostSettings, RecordOptions, Recorder};
+
+
+// Options for `--headless`, which runs the CPU simulation without a window or GPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessOptions {
+    pub steps: usize,
+    // Frames are written every `interval` steps, and always after the last one.
+    pub interval: usize,
+    pub output: PathBuf,
+    // Records every `record.every`th step as well, see `RecordOptions`.
+    pub record: Option<RecordOptions>,
+}
+
+impl HeadlessOptions {
+    // Returns `None` unless `--headless` was passed.
+    // Usage: physarum --headless [--steps N] [--interval N] [--output DIR] [--config DIR] [--record OUTPUT ...]
+    pub fn from_args(args: &[String]) -> GameResult<Option<HeadlessOptions>> {
+        if !args.iter().any(|arg| arg == "--headless") { return Ok(None); }
+
+        let mut options = HeadlessOptions { steps: 1000, interval: 0, output: PathBuf::from("output"), record: RecordOptions::from_args(args)? };
+        let mut args = args.iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().cloned().ok_or_else(|| GameError::CustomError(format!("Missing value for {}", name)));
+
+            match arg.as_str() {
+                "--headless" => {},
+                "--config" => { value("--config")?; },
+                "--steps" => options.steps = parse_count("--steps", &value("--steps")?)?,
+                "--interval" => options.interval = parse_count("--interval", &value("--interval")?)?,
+                "--output" => options.output = PathBuf::from(value("--output")?),
+                arg if RecordOptions::is_record_arg(arg) => { value(arg)?; },
+                _ => return Err(GameError::CustomError(format!("Unknown argument: {}", arg))),
+            }
+        }
+
+        if options.interval == 0 { options.interval = options.steps.max(1); }
+
+        return Ok(Some(options));
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> GameResult<usize> {
+    return value.parse().map_err(|_| GameError::CustomError(format!("{} expects a whole number, got {}", name, value)));
+}
+
+pub fn run_headless(options: &HeadlessOptions) -> GameResult {
+    let simulation_config = load::<SimulationConfig>("simulation")?;
+    let render_config = load_render_config(&config_root())?;
+    let palette = Palette::new(&render_config)?;
+    let post_settings = PostSettings::new(&render_config.post, 0.0)?;
+    let mut simulation = CpuSimulation::new(simulation_config)?;
+
+    return run_batch(&mut simulation, &palette, &post_settings, options);
+}
+
+pub fn run_batch(simulation: &mut CpuSimulation, palette: &Palette, post_settings: &PostSettings, options: &HeadlessOptions) -> GameResult {
+    fs::create_dir_all(&options.output)?;
+
+    let time_step = simulation.config.time_step;
+    let (width, height) = (simulation.config.width as u32, simulation.config.height as u32);
+    let mut recorder = match &options.record {
+        Some(record) => Some(Recorder::new(record.clone())?),
+        None => None,
+    };
+
+    for step in 1..=options.steps {
+        simulation.step(time_step)?;
+
+        if step % options.interval == 0 || step == options.steps {
+            write_frame(simulation, palette, post_settings, &options.output, step)?;
+        }
+
+        if let Some(recorder) = &mut recorder {
+            if !recorder.skip() {
+                let image = trail_map_to_rgba(&simulation.trail_map, &simulation.species, palette, post_settings, width, height);
+                recorder.push_frame(&image, width, height)?;
+            }
+        }
+    }
+
+    if let Some(recorder) = &mut recorder { recorder.finish()?; }
+
+    return Ok(());
+}
+
+// Writes `frame_<step>.png` and `frame_<step>.f32` into `directory`.
+pub fn write_frame(simulation: &CpuSimulation, palette: &Palette, post_settings: &PostSettings, directory: &Path, step: usize) -> GameResult {
+    let width = simulation.config.width as u32;
+    let height = simulation.config.height as u32;
+
+    let image = trail_map_to_rgba(&simulation.trail_map, &simulation.species, palette, post_settings, width, height);
+    write_png(&directory.join(format!("frame_{:06}.png", step)), &image, [width, height])?;
+
+    let raw_path = directory.join(format!("frame_{:06}.f32", step));
+    let mut raw = BufWriter::new(File::create(&raw_path)?);
+    raw.write_all(&trail_map_to_raw(&simulation.trail_map))?;
+    raw.flush()?;
+
+    return Ok(());
+}
+
+// Colours each cell like `render_map.wgsl` and runs the post chain over the
+// frame, one pixel per cell. Row 0 of the map is the bottom of the screen, so
+// rows are flipped to put it at the bottom of the image.
+pub fn trail_map_to_rgba(trail_map: &Vec<Trail>, species: &Vec<Species>, palette: &Palette, post_settings: &PostSettings, width: u32, height: u32) -> Vec<u8> {
+    let mut colors = Vec::with_capacity((width * height) as usize);
+
+    for y in (0..height).rev() {
+        for x in 0..width {
+            colors.push(shade(trail_map[(y * width + x) as usize].value, species, palette));
+        }
+    }
+
+    post_process(&mut colors, width as usize, height as usize, post_settings);
+
+    let mut image = Vec::with_capacity((width * height * 4) as usize);
+
+    for color in colors.iter() {
+        image.extend(color.iter().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+        image.push(255);
+    }
+
+    return image;
+}
+
+// Little endian f32s, `MAX_SPECIES` channels per cell in trail map order.
+pub fn trail_map_to_raw(trail_map: &Vec<Trail>) -> Vec<u8> {
+    return trail_map.iter().flat_map(|trail| trail.value).flat_map(|value| value.to_le_bytes()).collect();
+}
@@ -0,0 +1,66 @@
+use crate::{SensorKernel, DiffusionKernel};
+
+
+pub fn calculate_pixel_list(radius: i32) -> Vec<(i32, i32)> {
+    let mut pixel_list = vec![(0,0)];
+
+    if radius > 0 {
+        for y in -radius..radius + 1 {
+            for x in -radius..radius + 1{
+                let t = (x, y);
+                if t == (0,0) { continue; }
+
+                pixel_list.push(t);
+            }
+        }
+    }
+
+    return pixel_list;
+}
+
+// Must match `kernel_weight` in `update_agents.wgsl`.
+pub fn kernel_weight(kernel: SensorKernel, offset: (i32, i32), radius: i32) -> f32 {
+    let distance_squared = (offset.0 * offset.0 + offset.1 * offset.1) as f32;
+
+    return match kernel {
+        SensorKernel::Square => 1.0,
+        SensorKernel::Disc => if distance_squared <= (radius * radius) as f32 { 1.0 } else { 0.0 },
+        SensorKernel::Gaussian => {
+            let sigma = (radius as f32 / 2.0).max(0.5);
+            (-distance_squared / (2.0 * sigma * sigma)).exp()
+        },
+    };
+}
+
+// Offsets in cells with weights normalised to sum to 1.
+pub fn calculate_kernel(kernel: SensorKernel, sensor_size: f32) -> Vec<((i32, i32), f32)> {
+    let radius = sensor_size.max(0.0) as i32;
+
+    let mut weights: Vec<((i32, i32), f32)> = calculate_pixel_list(radius)
+        .into_iter()
+        .map(|offset| (offset, kernel_weight(kernel, offset, radius)))
+        .filter(|(_, weight)| *weight > 0.0)
+        .collect();
+
+    let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+    weights.iter_mut().for_each(|(_, weight)| *weight /= total);
+
+    return weights;
+}
+
+// One dimensional diffusion weights, 2D kernels are their outer product.
+// Must match `diffusion_weight` in `update_map.wgsl`.
+pub fn diffusion_weight(kernel: DiffusionKernel, offset: i32) -> f32 {
+    return match kernel {
+        DiffusionKernel::Box3 => 1.0,
+        DiffusionKernel::Gaussian5 => match offset.abs() {
+            0 => 6.0,
+            1 => 4.0,
+            _ => 1.0,
+        },
+        DiffusionKernel::SeparableGaussian(radius) => {
+            let sigma = (radius as f32 / 2.0).max(0.5);
+            (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp()
+        },
+    };
+}
@@ -4,36 +4,84 @@ use ggez::{ContextBuilder, event, conf::{WindowMode, WindowSetup, FullscreenType
 mod tests;
 
 mod agent;
+mod brush;
+mod camera;
 mod config;
 mod constants;
 mod construct;
+mod cpu_simulation;
 mod engine;
 mod enums;
+mod headless;
+mod kernel;
+mod mask;
+mod palette;
+mod panel;
 mod param;
+mod post;
+mod post_chain;
 mod programs;
+mod recorder;
+mod screenshot;
 mod simulation;
+mod snapshot;
 mod species;
 mod storage;
 mod trail;
 mod util;
+mod watcher;
 
 pub use agent::*;
+pub use brush::*;
+pub use camera::*;
 pub use config::*;
 pub use constants::*;
 pub use construct::*;
+pub use cpu_simulation::*;
 pub use engine::*;
 pub use enums::*;
+pub use headless::*;
+pub use kernel::*;
+pub use mask::*;
+pub use palette::*;
+pub use panel::*;
 pub use param::*;
+pub use post::*;
+pub use post_chain::*;
 pub use programs::*;
+pub use recorder::*;
+pub use screenshot::*;
 pub use simulation::*;
+pub use snapshot::*;
 pub use species::*;
 pub use storage::*;
 pub use trail::*;
 pub use util::*;
+pub use watcher::*;
 
 
 
 fn main() -> GameResult {
+    let args: Vec<String> = std::env::args().collect();
+    set_config_dir(config_dir_from_args(&args)?);
+
+    // `physarum restore SCREENSHOT [DIR]` writes out the configs saved in a screenshot.
+    if let Some((screenshot, dir)) = restore_args(&args)? {
+        restore_screenshot(&config_root(), &screenshot, &dir)?;
+        println!("Restored config to {}, run it with --config {}", dir.display(), dir.display());
+        return Ok(());
+    }
+
+    // Every run validates the configs first, `physarum validate [--config DIR]` stops there.
+    let errors = validate_all(&config_root());
+    if !errors.is_empty() || args.get(1).is_some_and(|arg| arg == "validate") {
+        return report_config_errors(&errors);
+    }
+
+    if let Some(options) = HeadlessOptions::from_args(&args)? {
+        return run_headless(&options);
+    }
+
     let window_config = load::<WindowConfig>("window")?;
     let window_mode = WindowMode::default()
         .fullscreen_type(if window_config.fullscreen { FullscreenType::True } else { FullscreenType::Windowed })
@@ -48,7 +96,20 @@ fn main() -> GameResult {
         .build()
         .expect("Context could not be created.");
 
-    let engine = Engine::new(&mut ctx)?;
+    let engine = Engine::new(&mut ctx, RecordOptions::from_args(&args)?)?;
 
     event::run(ctx, event_loop, engine);
 }
+
+fn report_config_errors(errors: &Vec<ConfigError>) -> GameResult {
+    if errors.is_empty() {
+        println!("Config at {:?} is valid", config_root());
+        return Ok(());
+    }
+
+    for error in errors.iter() {
+        eprintln!("{}", error);
+    }
+
+    return Err(config_errors_to_game_error(errors));
+}
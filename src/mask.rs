@@ -0,0 +1,61 @@
+use std::{fs::File};
+use rand::{Rng};
+use ggez::{GameResult, GameError};
+
+
+// Spawn mask decoded from a PNG, bright pixels are where agents may spawn.
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<(u32, u32)>,
+}
+
+impl Mask {
+    pub fn load(path: &str) -> GameResult<Mask> {
+        let file = File::open(path)?;
+
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info().map_err(|e| GameError::ResourceLoadError(format!("Failed to read mask {}: {}", path, e)))?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|e| GameError::ResourceLoadError(format!("Failed to decode mask {}: {}", path, e)))?;
+
+        let channels = info.color_type.samples();
+        let mut cells = Vec::new();
+
+        for y in 0..info.height {
+            for x in 0..info.width {
+                let offset = ((y * info.width + x) as usize) * channels;
+                let pixel = &data[offset..offset + channels];
+
+                let brightness = match info.color_type {
+                    png::ColorType::GrayscaleAlpha => pixel[0] as u32 * pixel[1] as u32 / 255,
+                    png::ColorType::Rgb => (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3,
+                    png::ColorType::Rgba => (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3 * pixel[3] as u32 / 255,
+                    _ => pixel[0] as u32,
+                };
+
+                if brightness > 127 { cells.push((x, y)); }
+            }
+        }
+
+        if cells.is_empty() {
+            return Err(GameError::ResourceLoadError(format!("Mask {} has no bright pixels to spawn agents on", path)));
+        }
+
+        let mask = Mask { width: info.width, height: info.height, cells };
+
+        return Ok(mask);
+    }
+
+    // Random position inside a random bright pixel, the top row of the image is the top of the map.
+    pub fn random_position<R: Rng + ?Sized>(&self, rng: &mut R) -> [f32; 2] {
+        let (x, y) = self.cells[rng.gen_range(0..self.cells.len())];
+
+        let position_x = (x as f32 + rng.gen::<f32>()) / self.width as f32 * 2.0 - 1.0;
+        let position_y = 1.0 - (y as f32 + rng.gen::<f32>()) / self.height as f32 * 2.0;
+
+        return [position_x, position_y];
+    }
+}
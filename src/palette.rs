@@ -0,0 +1,219 @@
+use ggez::{GameResult, GameError};
+use crate::{RenderConfig, ColorMap, AgentShape, AgentColoring, Agent, Species, MAX_SPECIES};
+
+
+// Must match the `stops` array length in `render_map.wgsl`.
+pub const MAX_GRADIENT_STOPS: usize = 16;
+
+// Evenly spaced samples of matplotlib's colour maps.
+const VIRIDIS: [&str; 9] = ["#440154", "#472d7b", "#3b528b", "#2c728e", "#21918c", "#28ae80", "#5ec962", "#addc30", "#fde725"];
+const MAGMA: [&str; 9] = ["#000004", "#1c1044", "#4f127b", "#812581", "#b5367a", "#e55064", "#fb8761", "#fec287", "#fcfdbf"];
+
+// Uniform for `render_map.wgsl`, built from a `RenderConfig`. Every colour map
+// other than `Species` is uploaded as gradient stops of `[r, g, b, position]`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Palette {
+    pub exposure: f32,
+    pub gamma: f32,
+    // 0 for per species colours, 1 for the gradient.
+    pub color_map: u32,
+    pub blend: u32,
+    pub stop_count: u32,
+    pub filter: u32,
+    pub _padding: [u32; 2],
+    pub stops: [[f32; 4]; MAX_GRADIENT_STOPS],
+}
+
+impl Palette {
+    pub fn new(render_config: &RenderConfig) -> GameResult<Palette> {
+        let gradient = gradient_stops(&render_config.color_map).map_err(GameError::ConfigError)?;
+        let mut stops = [[0.0; 4]; MAX_GRADIENT_STOPS];
+
+        for (stop, (position, color)) in stops.iter_mut().zip(gradient.iter()) {
+            *stop = [color[0], color[1], color[2], *position];
+        }
+
+        let palette = Palette {
+            exposure: render_config.exposure,
+            gamma: render_config.gamma,
+            color_map: if render_config.color_map == ColorMap::Species { 0 } else { 1 },
+            blend: render_config.blend.index(),
+            stop_count: gradient.len() as u32,
+            filter: render_config.filter.index(),
+            _padding: [0; 2],
+            stops,
+        };
+
+        return Ok(palette);
+    }
+}
+
+// Uniform for `render_agents.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct AgentStyle {
+    pub shape: u32,
+    pub coloring: u32,
+    pub subsample: u32,
+    // In pixels.
+    pub size: f32,
+    // Clip space units per pixel, 2 / window size.
+    pub pixel_size: [f32; 2],
+    pub _padding: [f32; 2],
+}
+
+impl AgentStyle {
+    pub fn new(render_config: &RenderConfig, window_size: [f32; 2]) -> GameResult<AgentStyle> {
+        let agent_style = AgentStyle {
+            shape: if render_config.agent_shape == AgentShape::Sprite { 1 } else { 0 },
+            coloring: if render_config.agent_coloring == AgentColoring::Heading { 1 } else { 0 },
+            subsample: render_config.agent_subsample.max(1),
+            size: render_config.agent_size,
+            pixel_size: [2.0 / window_size[0].max(1.0), 2.0 / window_size[1].max(1.0)],
+            _padding: [0.0; 2],
+        };
+
+        return Ok(agent_style);
+    }
+
+    // Instances drawn per tile, agent `i` is drawn by instance `i / subsample`.
+    pub fn drawn_agents(&self, agent_count: u32) -> u32 {
+        let subsample = self.subsample.max(1);
+
+        return agent_count / subsample + (agent_count % subsample != 0) as u32;
+    }
+}
+
+// Must match `agent_color` in `render_agents.wgsl`.
+pub fn agent_color(agent: &Agent, species: &Vec<Species>, agent_style: &AgentStyle) -> [f32; 3] {
+    if agent_style.coloring == 1 { return heading_color(agent.angle); }
+
+    return match species.get(agent.species as usize) {
+        Some(species) => [species.color_r, species.color_g, species.color_b],
+        None => [1.0; 3],
+    };
+}
+
+// Fully saturated hue going once around the colour wheel per turn, red at 0.
+pub fn heading_color(angle: f32) -> [f32; 3] {
+    let hue = (angle / std::f32::consts::TAU).rem_euclid(1.0) * 6.0;
+
+    return [5.0, 3.0, 1.0].map(|n: f32| {
+        let k = (n + hue) % 6.0;
+
+        return 1.0 - k.min(4.0 - k).clamp(0.0, 1.0);
+    });
+}
+
+// The stops of a colour map, empty for `ColorMap::Species`.
+pub fn gradient_stops(color_map: &ColorMap) -> Result<Vec<(f32, [f32; 3])>, String> {
+    let evenly_spaced = |colors: &[&str]| -> Result<Vec<(f32, [f32; 3])>, String> {
+        return colors.iter().enumerate()
+            .map(|(i, hex)| Ok((i as f32 / (colors.len() - 1) as f32, parse_hex_color(hex)?)))
+            .collect();
+    };
+
+    return match color_map {
+        ColorMap::Species => Ok(Vec::new()),
+        ColorMap::Viridis => evenly_spaced(&VIRIDIS),
+        ColorMap::Magma => evenly_spaced(&MAGMA),
+        ColorMap::Gradient(stops) => {
+            if stops.len() < 2 || stops.len() > MAX_GRADIENT_STOPS {
+                return Err(format!("Gradient needs 2 to {} stops, got {}", MAX_GRADIENT_STOPS, stops.len()));
+            }
+
+            let mut gradient = Vec::new();
+            let mut previous = 0.0;
+
+            for (position, hex) in stops.iter() {
+                if !(*position >= previous && *position <= 1.0) {
+                    return Err(format!("Gradient stop positions must rise from 0 to 1, got {} after {}", position, previous));
+                }
+                previous = *position;
+
+                gradient.push((*position, parse_hex_color(hex)?));
+            }
+
+            Ok(gradient)
+        },
+    };
+}
+
+// Accepts `#rrggbb` and `#rgb`, with or without the `#`.
+pub fn parse_hex_color(hex: &str) -> Result<[f32; 3], String> {
+    let digits = hex.trim().trim_start_matches('#');
+    let invalid = || format!("{} is not a hex colour like \"#ff8800\"", hex);
+
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) { return Err(invalid()); }
+
+    let channels: Vec<u8> = match digits.len() {
+        6 => (0..3).map(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap()).collect(),
+        3 => (0..3).map(|i| u8::from_str_radix(&digits[i..i + 1], 16).unwrap() * 17).collect(),
+        _ => return Err(invalid()),
+    };
+
+    return Ok([channels[0] as f32 / 255.0, channels[1] as f32 / 255.0, channels[2] as f32 / 255.0]);
+}
+
+pub fn color_to_hex(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+    return format!("#{:02x}{:02x}{:02x}", r, g, b);
+}
+
+// Colour of one trail cell. Must match `main_fs` in `render_map.wgsl`.
+pub fn shade(value: [f32; MAX_SPECIES], species: &Vec<Species>, palette: &Palette) -> [f32; 3] {
+    let species_count = species.len().min(MAX_SPECIES);
+    let mut color: [f32; 3] = [0.0; 3];
+
+    if palette.color_map == 0 {
+        let mut dominant = 0;
+
+        for (i, species) in species.iter().take(species_count).enumerate() {
+            let weighted = [species.color_r * value[i], species.color_g * value[i], species.color_b * value[i]];
+
+            match palette.blend {
+                1 => color = [color[0].max(weighted[0]), color[1].max(weighted[1]), color[2].max(weighted[2])],
+                2 => if value[i] > value[dominant] { dominant = i; },
+                _ => color = [color[0] + weighted[0], color[1] + weighted[1], color[2] + weighted[2]],
+            }
+        }
+
+        if palette.blend == 2 && species_count > 0 {
+            let species = &species[dominant];
+            color = [species.color_r * value[dominant], species.color_g * value[dominant], species.color_b * value[dominant]];
+        }
+
+        color = color.map(|c| c * palette.exposure);
+    } else {
+        let total: f32 = value.iter().take(species_count).sum();
+        color = sample_gradient(palette, total * palette.exposure);
+    }
+
+    return color.map(|c| c.max(0.0).powf(1.0 / palette.gamma));
+}
+
+// Must match `sample_gradient` in `render_map.wgsl`.
+pub fn sample_gradient(palette: &Palette, t: f32) -> [f32; 3] {
+    let count = (palette.stop_count as usize).min(MAX_GRADIENT_STOPS);
+    if count == 0 { return [0.0; 3]; }
+
+    let t = t.clamp(0.0, 1.0);
+    let stops = &palette.stops;
+    let rgb = |stop: &[f32; 4]| [stop[0], stop[1], stop[2]];
+
+    if t <= stops[0][3] { return rgb(&stops[0]); }
+
+    for i in 1..count {
+        let (a, b) = (&stops[i - 1], &stops[i]);
+
+        if t <= b[3] {
+            let f = if b[3] > a[3] { (t - a[3]) / (b[3] - a[3]) } else { 1.0 };
+
+            return [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f, a[2] + (b[2] - a[2]) * f];
+        }
+    }
+
+    return rgb(&stops[count - 1]);
+}
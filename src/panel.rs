@@ -0,0 +1,233 @@
+use ggez_egui::{egui};
+use crate::{SimulationConfig, SpeciesConfig, RenderConfig, MAX_INVOCATIONS, SensorKernel, DiffusionKernel, Boundary, BrushSettings, BrushTool, ColorMap, SpeciesBlend, TextureFilter, RenderMode, AgentShape, AgentColoring, ToneMap, PostConfig, MAX_BLOOM_RADIUS, color_to_hex};
+
+
+// What the user asked for this frame, applied by `Engine` once the panel closes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PanelActions {
+    // A value that can be uploaded in place changed.
+    pub changed: bool,
+    // `agent_count` changed and the buffers need reallocating.
+    pub rebuild: bool,
+    pub toggle_pause: bool,
+    pub step: bool,
+    pub reset: bool,
+    pub save: bool,
+}
+
+pub fn show_panel(ui: &mut egui::Ui, simulation_config: &mut SimulationConfig, species_configs: &mut Vec<SpeciesConfig>, paused: bool) -> PanelActions {
+    let mut actions = PanelActions::default();
+
+    ui.horizontal(|ui| {
+        actions.toggle_pause = ui.button(if paused { "Resume" } else { "Pause" }).clicked();
+        actions.step = ui.add_enabled(paused, egui::Button::new("Step")).clicked();
+        actions.reset = ui.button("Reset").clicked();
+        actions.save = ui.button("Save").clicked();
+    });
+
+    ui.separator();
+    ui.collapsing("Simulation", |ui| {
+        // Reallocates every buffer, so it only applies once the slider is let go.
+        let agent_count = ui.add(egui::Slider::new(&mut simulation_config.agent_count, 1..=MAX_INVOCATIONS).logarithmic(true).text("agent_count"));
+        actions.rebuild = agent_count.drag_released() || (agent_count.changed() && !agent_count.dragged());
+
+        let mut changed = false;
+        changed |= ui.add(egui::Slider::new(&mut simulation_config.diffusion_rate, 0.0..=1.0).text("diffusion_rate")).changed();
+        changed |= ui.add(egui::Slider::new(&mut simulation_config.decay_rate, 0.0..=1.0).text("decay_rate")).changed();
+        changed |= diffusion_kernel_picker(ui, &mut simulation_config.diffusion_kernel);
+
+        ui.horizontal(|ui| {
+            for (boundary, label) in [(Boundary::Wrap, "Wrap"), (Boundary::Reflect, "Reflect"), (Boundary::Absorb, "Absorb"), (Boundary::Clamp, "Clamp")] {
+                changed |= ui.radio_value(&mut simulation_config.boundary, boundary, label).changed();
+            }
+        });
+
+        changed |= ui.add(egui::Slider::new(&mut simulation_config.substeps, 1..=16).text("substeps")).changed();
+        changed |= ui.add(egui::Slider::new(&mut simulation_config.time_scale, 0.0..=4.0).text("time_scale")).changed();
+
+        actions.changed |= changed;
+    });
+
+    let species_count = species_configs.len();
+
+    for (index, species_config) in species_configs.iter_mut().enumerate() {
+        ui.collapsing(format!("Species {}", index), |ui| {
+            actions.changed |= species_sliders(ui, species_config, index, species_count);
+        });
+    }
+
+    return actions;
+}
+
+// Hold the left mouse button over the map to use the brush.
+pub fn show_brush_panel(ui: &mut egui::Ui, brush_settings: &mut BrushSettings, species_count: usize) {
+    ui.collapsing("Brush", |ui| {
+        ui.horizontal(|ui| {
+            for (tool, label) in [(BrushTool::Paint, "Paint"), (BrushTool::Erase, "Erase"), (BrushTool::Spawn, "Spawn"), (BrushTool::Push, "Push")] {
+                ui.radio_value(&mut brush_settings.tool, tool, label);
+            }
+        });
+
+        ui.add(egui::Slider::new(&mut brush_settings.radius, 0.005..=0.5).logarithmic(true).text("radius"));
+        ui.add(egui::Slider::new(&mut brush_settings.strength, 0.0..=2.0).text("strength"));
+        ui.add(egui::Slider::new(&mut brush_settings.species, 0..=species_count.max(1) as u32 - 1).text("species"));
+        ui.add(egui::Slider::new(&mut brush_settings.spawn_count, 1..=10_000).logarithmic(true).text("spawn_count"));
+    });
+}
+
+// Returns whether anything changed, the palette is rebuilt from `render_config`.
+pub fn show_render_panel(ui: &mut egui::Ui, render_config: &mut RenderConfig) -> bool {
+    let mut changed = false;
+
+    ui.collapsing("Rendering", |ui| {
+        // Gradients are only defined in `render.ron`, reload it to get one back.
+        let mut color_maps = vec![(ColorMap::Species, "Species"), (ColorMap::Viridis, "Viridis"), (ColorMap::Magma, "Magma")];
+        if let ColorMap::Gradient(_) = render_config.color_map { color_maps.push((render_config.color_map.clone(), "Gradient")); }
+
+        ui.horizontal(|ui| {
+            for (color_map, label) in color_maps {
+                changed |= ui.radio_value(&mut render_config.color_map, color_map, label).changed();
+            }
+        });
+
+        ui.add_enabled_ui(render_config.color_map == ColorMap::Species, |ui| {
+            ui.horizontal(|ui| {
+                for (blend, label) in [(SpeciesBlend::Additive, "Additive"), (SpeciesBlend::Max, "Max"), (SpeciesBlend::Dominant, "Dominant")] {
+                    changed |= ui.radio_value(&mut render_config.blend, blend, label).changed();
+                }
+            });
+        });
+
+        changed |= ui.add(egui::Slider::new(&mut render_config.exposure, 0.0..=16.0).logarithmic(true).text("exposure")).changed();
+        changed |= ui.add(egui::Slider::new(&mut render_config.gamma, 0.1..=4.0).text("gamma")).changed();
+
+        ui.horizontal(|ui| {
+            for (filter, label) in [(TextureFilter::Nearest, "Nearest"), (TextureFilter::Linear, "Linear")] {
+                changed |= ui.radio_value(&mut render_config.filter, filter, label).changed();
+            }
+        });
+        changed |= ui.checkbox(&mut render_config.keep_aspect, "keep_aspect").changed();
+
+        // Agent settings are picked up every frame, they don't touch the palette.
+        ui.separator();
+        ui.horizontal(|ui| {
+            for (mode, label) in [(RenderMode::Trail, "Trail"), (RenderMode::Agents, "Agents"), (RenderMode::Both, "Both")] {
+                ui.radio_value(&mut render_config.mode, mode, label);
+            }
+        });
+
+        ui.add_enabled_ui(render_config.mode.shows_agents(), |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut render_config.agent_shape, AgentShape::Point, "Point");
+                ui.radio_value(&mut render_config.agent_shape, AgentShape::Sprite, "Sprite");
+                ui.separator();
+                ui.radio_value(&mut render_config.agent_coloring, AgentColoring::Species, "Species");
+                ui.radio_value(&mut render_config.agent_coloring, AgentColoring::Heading, "Heading");
+            });
+
+            ui.add(egui::Slider::new(&mut render_config.agent_subsample, 1..=1000).logarithmic(true).text("agent_subsample"));
+            ui.add(egui::Slider::new(&mut render_config.agent_size, 1.0..=16.0).text("agent_size"));
+        });
+
+        ui.separator();
+        post_sliders(ui, &mut render_config.post);
+    });
+
+    return changed;
+}
+
+// The post chain reads its settings every frame, so nothing needs reporting.
+fn post_sliders(ui: &mut egui::Ui, post_config: &mut PostConfig) {
+    ui.horizontal(|ui| {
+        for (tone_map, label) in [(ToneMap::None, "None"), (ToneMap::Reinhard, "Reinhard"), (ToneMap::Aces, "ACES"), (ToneMap::Filmic, "Filmic")] {
+            ui.radio_value(&mut post_config.tone_map, tone_map, label);
+        }
+    });
+
+    let exposure = &mut post_config.exposure;
+    ui.checkbox(&mut exposure.auto, "auto exposure");
+    ui.add(egui::Slider::new(&mut exposure.compensation, -8.0..=8.0).text("compensation"));
+    ui.add_enabled_ui(exposure.auto, |ui| {
+        ui.add(egui::Slider::new(&mut exposure.key, 0.01..=1.0).logarithmic(true).text("key"));
+        ui.add(egui::Slider::new(&mut exposure.adaptation_rate, 0.0..=10.0).text("adaptation_rate"));
+    });
+
+    let bloom = &mut post_config.bloom;
+    ui.checkbox(&mut bloom.enabled, "bloom");
+    ui.add_enabled_ui(bloom.enabled, |ui| {
+        ui.add(egui::Slider::new(&mut bloom.threshold, 0.0..=4.0).text("threshold"));
+        ui.add(egui::Slider::new(&mut bloom.intensity, 0.0..=4.0).text("intensity"));
+        ui.add(egui::Slider::new(&mut bloom.radius, 0..=MAX_BLOOM_RADIUS).text("radius"));
+    });
+
+    let vignette = &mut post_config.vignette;
+    ui.checkbox(&mut vignette.enabled, "vignette");
+    ui.add_enabled_ui(vignette.enabled, |ui| {
+        ui.add(egui::Slider::new(&mut vignette.strength, 0.0..=1.0).text("strength"));
+        ui.add(egui::Slider::new(&mut vignette.radius, 0.0..=1.5).text("radius"));
+        ui.add(egui::Slider::new(&mut vignette.softness, 0.01..=1.5).text("softness"));
+    });
+}
+
+fn diffusion_kernel_picker(ui: &mut egui::Ui, diffusion_kernel: &mut DiffusionKernel) -> bool {
+    let mut changed = false;
+    let mut radius = match diffusion_kernel { DiffusionKernel::SeparableGaussian(radius) => *radius, _ => 3 };
+
+    ui.horizontal(|ui| {
+        changed |= ui.radio_value(diffusion_kernel, DiffusionKernel::Box3, "Box3").changed();
+        changed |= ui.radio_value(diffusion_kernel, DiffusionKernel::Gaussian5, "Gaussian5").changed();
+        changed |= ui.radio_value(diffusion_kernel, DiffusionKernel::SeparableGaussian(radius), "SeparableGaussian").changed();
+    });
+
+    if let DiffusionKernel::SeparableGaussian(_) = diffusion_kernel {
+        if ui.add(egui::Slider::new(&mut radius, 1..=16).text("radius")).changed() {
+            *diffusion_kernel = DiffusionKernel::SeparableGaussian(radius);
+            changed = true;
+        }
+    }
+
+    return changed;
+}
+
+fn species_sliders(ui: &mut egui::Ui, config: &mut SpeciesConfig, index: usize, species_count: usize) -> bool {
+    let mut changed = false;
+
+    changed |= ui.add(egui::Slider::new(&mut config.sensor_size, 0.0..=5.0).text("sensor_size")).changed();
+
+    ui.horizontal(|ui| {
+        for (kernel, label) in [(SensorKernel::Square, "Square"), (SensorKernel::Disc, "Disc"), (SensorKernel::Gaussian, "Gaussian")] {
+            changed |= ui.radio_value(&mut config.sensor_kernel, kernel, label).changed();
+        }
+    });
+
+    changed |= ui.add(egui::Slider::new(&mut config.sensor_angle, -360.0..=360.0).text("sensor_angle")).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.sensor_distance, 0.0..=0.2).text("sensor_distance")).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.turn_speed, 0.0..=10.0).text("turn_speed")).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.move_speed, 0.0..=2.0).text("move_speed")).changed();
+    // Any finite bias is valid, dragging covers the usual range and typing the rest.
+    changed |= ui.add(egui::Slider::new(&mut config.forward_bias, -5.0..=5.0).clamp_to_range(false).text("forward_bias")).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.left_bias, -5.0..=5.0).clamp_to_range(false).text("left_bias")).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.right_bias, -5.0..=5.0).clamp_to_range(false).text("right_bias")).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.weight, 0.0..=1.0).text("weight")).changed();
+
+    let mut color = config.rgb().unwrap_or([config.color_r, config.color_g, config.color_b]);
+    ui.horizontal(|ui| {
+        ui.label("color");
+        if ui.color_edit_button_rgb(&mut color).changed() {
+            config.color_r = color[0];
+            config.color_g = color[1];
+            config.color_b = color[2];
+            // Keeps a hex colour from the config in hex form when saved.
+            if config.color.is_some() { config.color = Some(color_to_hex(color)); }
+            changed = true;
+        }
+    });
+
+    config.expand_interactions(index, species_count);
+
+    for (other, interaction) in config.interactions.iter_mut().enumerate() {
+        changed |= ui.add(egui::Slider::new(interaction, -2.0..=2.0).text(format!("interaction with {}", other))).changed();
+    }
+
+    return changed;
+}
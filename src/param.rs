@@ -1,6 +1,23 @@
+// Upper bound on simulation steps encoded into a single rendered frame.
+pub const MAX_STEPS_PER_FRAME: usize = 64;
+
 #[repr(C)]
 #[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Param {
     pub delta_time: f32,
     pub frame: u32,
 }
+
+// Steps owed for one rendered frame and the accumulator left after it:
+// `substeps` scaled by `time_scale`, carrying fractional steps over so slow
+// motion still advances. A frame owing more than `MAX_STEPS_PER_FRAME` runs the
+// cap and drops the rest along with the fraction, so a slow frame doesn't keep
+// the following ones behind.
+pub fn steps_for(accumulator: f32, substeps: u32, time_scale: f32) -> (usize, f32) {
+    let accumulator = accumulator + substeps as f32 * time_scale.max(0.0);
+    let steps = accumulator.floor() as usize;
+
+    if steps > MAX_STEPS_PER_FRAME { return (MAX_STEPS_PER_FRAME, 0.0); }
+
+    return (steps, accumulator - steps as f32);
+}
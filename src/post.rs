@@ -0,0 +1,245 @@
+use ggez::{GameResult};
+use crate::{PostConfig};
+
+
+// Must match the loop bound in `bloom.wgsl`.
+pub const MAX_BLOOM_RADIUS: u32 = 32;
+
+// Must match the `bins` array length in `exposure.wgsl`.
+pub const HISTOGRAM_BINS: usize = 64;
+
+// Luminance below this is treated as background and left out of the histogram.
+pub const MIN_LUMINANCE: f32 = 1e-5;
+
+// Uniform shared by `bloom.wgsl`, `exposure.wgsl` and `post.wgsl`, built from
+// the `post` section of `render.ron` each frame.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PostSettings {
+    pub tone_map: u32,
+    pub auto_exposure: u32,
+    // 2^compensation, the exposure on its own when `auto_exposure` is off.
+    pub exposure_scale: f32,
+    pub key: f32,
+    pub min_log_luminance: f32,
+    pub log_luminance_range: f32,
+    pub adaptation_rate: f32,
+    // Seconds since the previous frame, for the exposure adaptation.
+    pub delta_time: f32,
+    pub bloom: u32,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub bloom_radius: u32,
+    pub vignette: u32,
+    pub vignette_strength: f32,
+    pub vignette_radius: f32,
+    pub vignette_softness: f32,
+}
+
+impl PostSettings {
+    pub fn new(post_config: &PostConfig, delta_time: f32) -> GameResult<PostSettings> {
+        let exposure = &post_config.exposure;
+        let bloom = &post_config.bloom;
+        let vignette = &post_config.vignette;
+
+        let post_settings = PostSettings {
+            tone_map: post_config.tone_map.index(),
+            auto_exposure: exposure.auto as u32,
+            exposure_scale: exposure.compensation.exp2(),
+            key: exposure.key,
+            min_log_luminance: exposure.min_log_luminance,
+            log_luminance_range: (exposure.max_log_luminance - exposure.min_log_luminance).max(0.001),
+            adaptation_rate: exposure.adaptation_rate,
+            delta_time,
+            bloom: (bloom.enabled && bloom.intensity > 0.0) as u32,
+            bloom_threshold: bloom.threshold,
+            bloom_intensity: bloom.intensity,
+            bloom_radius: bloom.radius.min(MAX_BLOOM_RADIUS),
+            vignette: vignette.enabled as u32,
+            vignette_strength: vignette.strength,
+            vignette_radius: vignette.radius,
+            vignette_softness: vignette.softness.max(0.001),
+        };
+
+        return Ok(post_settings);
+    }
+
+    // Exposure that puts `luminance` on the key, or the fixed exposure.
+    // Must match `average` in `exposure.wgsl`.
+    pub fn exposure(&self, luminance: f32) -> f32 {
+        if self.auto_exposure == 0 { return self.exposure_scale; }
+
+        return self.key / luminance.max(MIN_LUMINANCE) * self.exposure_scale;
+    }
+
+    // Moves the adapted luminance towards this frame's average.
+    pub fn adapt(&self, adapted: f32, luminance: f32) -> f32 {
+        if adapted <= 0.0 || self.adaptation_rate <= 0.0 { return luminance; }
+
+        return adapted + (luminance - adapted) * (1.0 - (-self.delta_time * self.adaptation_rate).exp());
+    }
+}
+
+pub fn luminance(color: [f32; 3]) -> f32 {
+    return color[0] * 0.2126 + color[1] * 0.7152 + color[2] * 0.0722;
+}
+
+// Bin of a pixel in the exposure histogram, `None` for the background.
+// Must match `histogram` in `exposure.wgsl`.
+pub fn histogram_bin(color: [f32; 3], post_settings: &PostSettings) -> Option<usize> {
+    let luminance = luminance(color);
+    if !(luminance >= MIN_LUMINANCE) { return None; }
+
+    let t = ((luminance.log2() - post_settings.min_log_luminance) / post_settings.log_luminance_range).clamp(0.0, 1.0);
+
+    return Some(((t * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1));
+}
+
+// Geometric mean luminance of the counted pixels, from the bin centres.
+// Must match `average` in `exposure.wgsl`.
+pub fn histogram_luminance(bins: &[u32; HISTOGRAM_BINS], post_settings: &PostSettings) -> Option<f32> {
+    let count: u32 = bins.iter().sum();
+    if count == 0 { return None; }
+
+    let weighted: f32 = bins.iter().enumerate().map(|(i, bin)| (i as f32 + 0.5) * *bin as f32).sum();
+    let log_luminance = weighted / count as f32 / HISTOGRAM_BINS as f32 * post_settings.log_luminance_range + post_settings.min_log_luminance;
+
+    return Some(log_luminance.exp2());
+}
+
+// Must match `tone_map` in `post.wgsl`.
+pub fn tone_map(color: [f32; 3], post_settings: &PostSettings) -> [f32; 3] {
+    let color = color.map(|c| c.max(0.0));
+
+    return match post_settings.tone_map {
+        1 => color.map(|c| c / (1.0 + c)),
+        2 => color.map(|c| (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).map(|c| c.clamp(0.0, 1.0)),
+        3 => color.map(|c| (hable(c * 2.0) / hable(11.2)).min(1.0)),
+        _ => color.map(|c| c.min(1.0)),
+    };
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+
+    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
+
+// Brightness left at `uv` in [0, 1] across the window. Must match `vignette`
+// in `post.wgsl`.
+pub fn vignette(uv: [f32; 2], post_settings: &PostSettings) -> f32 {
+    if post_settings.vignette == 0 { return 1.0; }
+
+    let distance = ((uv[0] - 0.5).powi(2) + (uv[1] - 0.5).powi(2)).sqrt() * std::f32::consts::SQRT_2;
+    let t = ((distance - post_settings.vignette_radius) / post_settings.vignette_softness).clamp(0.0, 1.0);
+
+    return 1.0 - post_settings.vignette_strength * t * t * (3.0 - 2.0 * t);
+}
+
+// Gaussian weights for offsets 0 to `radius`, normalised over both sides.
+// Must match `blur` in `bloom.wgsl`.
+pub fn bloom_weights(radius: u32) -> Vec<f32> {
+    let sigma = (radius as f32 / 2.0).max(0.5);
+    let weights: Vec<f32> = (0..=radius).map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+
+    return weights.iter().map(|weight| weight / total).collect();
+}
+
+// Runs the whole post chain on an image of linear colours, rows top to bottom,
+// like `PostChain` does on the GPU. Exposure is measured on this image alone
+// with no adaptation. Bloom is upsampled from half resolution bilinearly.
+pub fn post_process(image: &mut Vec<[f32; 3]>, width: usize, height: usize, post_settings: &PostSettings) {
+    let bloom = if post_settings.bloom != 0 { Some(bloom_image(image, width, height, post_settings)) } else { None };
+
+    let mut bins = [0; HISTOGRAM_BINS];
+    if post_settings.auto_exposure != 0 {
+        for color in image.iter() {
+            if let Some(bin) = histogram_bin(*color, post_settings) { bins[bin] += 1; }
+        }
+    }
+    let exposure = post_settings.exposure(histogram_luminance(&bins, post_settings).unwrap_or(post_settings.key));
+
+    for y in 0..height {
+        for x in 0..width {
+            let uv = [(x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32];
+            let mut color = image[y * width + x];
+
+            if let Some((bloom, bloom_width, bloom_height)) = &bloom {
+                let glow = sample_bilinear(bloom, *bloom_width, *bloom_height, uv);
+
+                color = [0, 1, 2].map(|c| color[c] + glow[c] * post_settings.bloom_intensity);
+            }
+
+            let factor = vignette(uv, post_settings);
+
+            image[y * width + x] = tone_map(color.map(|c| c * exposure), post_settings).map(|c| c * factor);
+        }
+    }
+}
+
+// Half resolution bright pass blurred on both axes. Must match `bloom.wgsl`.
+fn bloom_image(image: &Vec<[f32; 3]>, width: usize, height: usize, post_settings: &PostSettings) -> (Vec<[f32; 3]>, usize, usize) {
+    let (bloom_width, bloom_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut bright = vec![[0.0; 3]; bloom_width * bloom_height];
+
+    for y in 0..bloom_height {
+        for x in 0..bloom_width {
+            let mut color = [0.0; 3];
+
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let pixel = image[(y * 2 + dy).min(height - 1) * width + (x * 2 + dx).min(width - 1)];
+                color = [0, 1, 2].map(|c| color[c] + pixel[c] * 0.25);
+            }
+
+            let luminance = luminance(color);
+            let scale = (luminance - post_settings.bloom_threshold).max(0.0) / luminance.max(MIN_LUMINANCE);
+
+            bright[y * bloom_width + x] = color.map(|c| c * scale);
+        }
+    }
+
+    let weights = bloom_weights(post_settings.bloom_radius);
+    let horizontal = blur(&bright, bloom_width, bloom_height, &weights, (1, 0));
+    let vertical = blur(&horizontal, bloom_width, bloom_height, &weights, (0, 1));
+
+    return (vertical, bloom_width, bloom_height);
+}
+
+fn blur(image: &Vec<[f32; 3]>, width: usize, height: usize, weights: &Vec<f32>, direction: (i32, i32)) -> Vec<[f32; 3]> {
+    let mut blurred = vec![[0.0; 3]; width * height];
+    let radius = weights.len() as i32 - 1;
+
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let mut color = [0.0; 3];
+
+            for i in -radius..=radius {
+                let sx = (x + direction.0 * i).clamp(0, width as i32 - 1);
+                let sy = (y + direction.1 * i).clamp(0, height as i32 - 1);
+                let pixel = image[(sy * width as i32 + sx) as usize];
+                let weight = weights[i.unsigned_abs() as usize];
+
+                color = [0, 1, 2].map(|c| color[c] + pixel[c] * weight);
+            }
+
+            blurred[(y * width as i32 + x) as usize] = color;
+        }
+    }
+
+    return blurred;
+}
+
+// Like a linear, clamp to edge sampler.
+fn sample_bilinear(image: &Vec<[f32; 3]>, width: usize, height: usize, uv: [f32; 2]) -> [f32; 3] {
+    let x = (uv[0] * width as f32 - 0.5).clamp(0.0, (width - 1) as f32);
+    let y = (uv[1] * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let pixel = |x: usize, y: usize| image[y * width + x];
+    let (a, b, c, d) = (pixel(x0, y0), pixel(x1, y0), pixel(x0, y1), pixel(x1, y1));
+
+    return [0, 1, 2].map(|i| (a[i] * (1.0 - fx) + b[i] * fx) * (1.0 - fy) + (c[i] * (1.0 - fx) + d[i] * fx) * fy);
+}
@@ -0,0 +1,175 @@
+use std::num::NonZeroU32;
+use ggez::{Context, GameResult};
+use crate::{construct::*, util, Storage, TextureStorage, ComputeProgram, RenderProgram, PostSettings, CAPTURE_TEXTURE_FORMAT};
+
+
+// The frame is drawn into `hdr_texture`, then exposed, bloomed, tone mapped and
+// vignetted onto the window by `process`. Every texture is the window's size,
+// so the chain is rebuilt when it changes.
+pub struct PostChain {
+    pub size: [u32; 2],
+    pub post_settings: PostSettings,
+
+    pub hdr_texture: TextureStorage,
+    pub bloom_textures: Vec<TextureStorage>,
+    pub post_settings_storage: Storage,
+    pub exposure_storage: Storage,
+
+    pub bright_program: ComputeProgram,
+    pub blur_horizontal_program: ComputeProgram,
+    pub blur_vertical_program: ComputeProgram,
+    pub histogram_program: ComputeProgram,
+    pub average_program: ComputeProgram,
+    pub post_program: RenderProgram,
+
+    // Only while recording.
+    pub capture: Option<FrameCapture>,
+}
+
+// A second copy of the chain's output without the UI, copied into `storage`
+// and read back once the frame has been submitted.
+pub struct FrameCapture {
+    pub texture: TextureStorage,
+    pub storage: Storage,
+    pub program: RenderProgram,
+    // A frame was copied into `storage` and hasn't been read yet.
+    pub pending: bool,
+}
+
+impl PostChain {
+    pub fn new(ctx: &mut Context, size: [u32; 2], post_settings: PostSettings) -> GameResult<PostChain> {
+        let device = &ctx.gfx.wgpu().device;
+        let size = [size[0].max(1), size[1].max(1)];
+        let bloom_size = [(size[0] / 2).max(1), (size[1] / 2).max(1)];
+
+        let hdr_texture           = construct_hdr_texture(device, size)?;
+        let bloom_textures        = construct_bloom_textures(device, size)?;
+        let post_settings_storage = construct_post_settings_storage(device, &post_settings)?;
+        let exposure_storage      = construct_exposure_storage(device)?;
+
+        let bright_program          = construct_bloom_program(ctx, &post_settings_storage, &hdr_texture, &bloom_textures[0], bloom_size, "bright_pass")?;
+        let blur_horizontal_program = construct_bloom_program(ctx, &post_settings_storage, &bloom_textures[0], &bloom_textures[1], bloom_size, "blur_horizontal")?;
+        let blur_vertical_program   = construct_bloom_program(ctx, &post_settings_storage, &bloom_textures[1], &bloom_textures[0], bloom_size, "blur_vertical")?;
+        let histogram_program       = construct_exposure_program(ctx, &post_settings_storage, &hdr_texture, &exposure_storage, size, "histogram")?;
+        let average_program         = construct_exposure_program(ctx, &post_settings_storage, &hdr_texture, &exposure_storage, size, "average")?;
+        let surface_format          = ctx.gfx.surface_format();
+        let post_program            = construct_post_program(ctx, &post_settings_storage, &hdr_texture, &bloom_textures[0], &exposure_storage, surface_format)?;
+
+        let post_chain = PostChain {
+            size                   ,
+            post_settings          ,
+            hdr_texture            ,
+            bloom_textures         ,
+            post_settings_storage  ,
+            exposure_storage       ,
+            bright_program         ,
+            blur_horizontal_program,
+            blur_vertical_program  ,
+            histogram_program      ,
+            average_program        ,
+            post_program           ,
+            capture: None          ,
+        };
+
+        return Ok(post_chain);
+    }
+
+    pub fn set_post_settings(&mut self, ctx: &mut Context, post_settings: PostSettings) -> GameResult {
+        ctx.gfx.wgpu().queue.write_buffer(&self.post_settings_storage.buffer, 0, bytemuck::cast_slice(&[post_settings]));
+
+        self.post_settings = post_settings;
+
+        return Ok(());
+    }
+
+    pub fn set_capturing(&mut self, ctx: &mut Context, capturing: bool) -> GameResult {
+        if capturing == self.capture.is_some() { return Ok(()); }
+        if !capturing {
+            self.capture = None;
+            return Ok(());
+        }
+
+        let device = &ctx.gfx.wgpu().device;
+        let texture = construct_capture_texture(device, self.size)?;
+        let storage = construct_capture_storage(device, self.size)?;
+        let program = construct_post_program(ctx, &self.post_settings_storage, &self.hdr_texture, &self.bloom_textures[0], &self.exposure_storage, CAPTURE_TEXTURE_FORMAT)?;
+
+        self.capture = Some(FrameCapture { texture, storage, program, pending: false });
+
+        return Ok(());
+    }
+
+    // The frame copied by the last `process` with `capture`, as RGBA8 rows from
+    // the top. Call between frames so the copy has been submitted.
+    pub fn read_capture(&mut self, ctx: &mut Context) -> GameResult<Option<Vec<u8>>> {
+        let capture = match &mut self.capture {
+            Some(capture) if capture.pending => capture,
+            _ => return Ok(None),
+        };
+        capture.pending = false;
+
+        let data: Vec<u8> = util::read_buffer(&ctx.gfx.wgpu().device, &ctx.gfx.wgpu().queue, &capture.storage.buffer, capture.storage.size)?;
+        let bytes_per_row = capture_bytes_per_row(self.size[0]) as usize;
+        let row_size = self.size[0] as usize * 4;
+
+        return Ok(Some(data.chunks_exact(bytes_per_row).flat_map(|row| row[..row_size].iter().copied()).collect()));
+    }
+
+    // Encodes the chain from `hdr_texture` onto `view`, the window's frame. With
+    // `capture` the output is also copied for `read_capture`.
+    pub fn process(&mut self, command_encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, capture: bool) -> GameResult {
+        command_encoder.push_debug_group("Post Process");
+        {
+            // Every program has a single bind group.
+            if self.post_settings.auto_exposure != 0 { self.histogram_program.process(command_encoder, 0)?; }
+            self.average_program.process(command_encoder, 0)?;
+
+            if self.post_settings.bloom != 0 {
+                self.bright_program.process(command_encoder, 0)?;
+                self.blur_horizontal_program.process(command_encoder, 0)?;
+                self.blur_vertical_program.process(command_encoder, 0)?;
+            }
+
+            let color_attachments = &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }];
+
+            self.post_program.process(command_encoder, color_attachments, vec![], 0..3, 0..1, 0)?;
+
+            if let Some(frame_capture) = self.capture.as_mut().filter(|_| capture) {
+                let color_attachments = &[wgpu::RenderPassColorAttachment {
+                    view: &frame_capture.texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }];
+
+                frame_capture.program.process(command_encoder, color_attachments, vec![], 0..3, 0..1, 0)?;
+
+                command_encoder.copy_texture_to_buffer(
+                    frame_capture.texture.texture.as_image_copy(),
+                    wgpu::ImageCopyBuffer {
+                        buffer: &frame_capture.storage.buffer,
+                        layout: wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: NonZeroU32::new(capture_bytes_per_row(self.size[0])),
+                            rows_per_image: None,
+                        },
+                    },
+                    wgpu::Extent3d { width: self.size[0], height: self.size[1], depth_or_array_layers: 1 },
+                );
+                frame_capture.pending = true;
+            }
+        }
+        command_encoder.pop_debug_group();
+
+        return Ok(());
+    }
+}
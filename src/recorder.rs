@@ -0,0 +1,199 @@
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+use ggez::{GameResult, GameError};
+use crate::{RecordFormat};
+
+
+// Options for `--record`, shared by the window and `--headless`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordOptions {
+    pub format: RecordFormat,
+    // A directory for a PNG sequence, a file for an APNG, `-` for stdout.
+    pub output: PathBuf,
+    // Keeps every `every`th rendered frame, or simulation step when headless.
+    pub every: usize,
+    // Stops after this many frames are written, records until stopped if `None`.
+    pub frames: Option<usize>,
+    // Frames are scaled to this size, the first frame's size if `None`.
+    pub size: Option<[u32; 2]>,
+    // Playback rate of an APNG.
+    pub fps: u32,
+}
+
+impl RecordOptions {
+    // Returns `None` unless `--record` was passed. The format follows from the
+    // output unless given: `-` streams raw frames, `.png` files are animated.
+    // Usage: --record OUTPUT [--record-format png|apng|raw] [--record-every N]
+    //        [--record-frames N] [--record-size WxH] [--record-fps N]
+    pub fn from_args(args: &[String]) -> GameResult<Option<RecordOptions>> {
+        let value = |name: &str| -> GameResult<Option<String>> {
+            return match args.iter().position(|arg| arg == name) {
+                Some(index) => args.get(index + 1).cloned().map(Some).ok_or_else(|| GameError::CustomError(format!("Missing value for {}", name))),
+                None => Ok(None),
+            };
+        };
+
+        let output = match value("--record")? {
+            Some(output) => PathBuf::from(output),
+            None => return Ok(None),
+        };
+
+        let format = match value("--record-format")?.as_deref() {
+            Some("png") => RecordFormat::PngSequence,
+            Some("apng") => RecordFormat::Apng,
+            Some("raw") => RecordFormat::Raw,
+            Some(format) => return Err(GameError::CustomError(format!("--record-format expects png, apng or raw, got {}", format))),
+            None if output == Path::new("-") => RecordFormat::Raw,
+            None if output.extension().is_some_and(|extension| extension == "png" || extension == "apng") => RecordFormat::Apng,
+            None => RecordFormat::PngSequence,
+        };
+
+        let mut options = RecordOptions { format, output, every: 1, frames: None, size: None, fps: 30 };
+
+        if let Some(every) = value("--record-every")? { options.every = parse_positive("--record-every", &every)?; }
+        if let Some(frames) = value("--record-frames")? { options.frames = Some(parse_positive("--record-frames", &frames)?); }
+        if let Some(fps) = value("--record-fps")? { options.fps = parse_positive("--record-fps", &fps)? as u32; }
+        if let Some(size) = value("--record-size")? { options.size = Some(parse_size(&size)?); }
+
+        return Ok(Some(options));
+    }
+
+    // The flags `from_args` reads, each followed by a value.
+    pub fn is_record_arg(arg: &str) -> bool {
+        return ["--record", "--record-format", "--record-every", "--record-frames", "--record-size", "--record-fps"].contains(&arg);
+    }
+}
+
+fn parse_positive(name: &str, value: &str) -> GameResult<usize> {
+    return value.parse().ok().filter(|value| *value > 0).ok_or_else(|| GameError::CustomError(format!("{} expects a number above 0, got {}", name, value)));
+}
+
+fn parse_size(value: &str) -> GameResult<[u32; 2]> {
+    let invalid = || GameError::CustomError(format!("--record-size expects WIDTHxHEIGHT like 1280x720, got {}", value));
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let size = [width.parse().map_err(|_| invalid())?, height.parse().map_err(|_| invalid())?];
+
+    if size[0] == 0 || size[1] == 0 { return Err(invalid()); }
+
+    return Ok(size);
+}
+
+// Writes the frames it is given in the format of its `RecordOptions`. Call
+// `skip` once per candidate frame and only push the ones it keeps.
+pub struct Recorder {
+    pub options: RecordOptions,
+    pub frames_seen: usize,
+    pub frames_written: usize,
+    size: Option<[u32; 2]>,
+    // APNG frames, the animation header needs the frame count up front.
+    frames: Vec<Vec<u8>>,
+    // Stdout or the output file for raw frames.
+    raw: Option<Box<dyn Write>>,
+}
+
+impl Recorder {
+    pub fn new(options: RecordOptions) -> GameResult<Recorder> {
+        if options.format == RecordFormat::PngSequence { fs::create_dir_all(&options.output)?; }
+
+        let raw: Option<Box<dyn Write>> = match options.format {
+            RecordFormat::Raw if options.output == Path::new("-") => Some(Box::new(BufWriter::new(io::stdout()))),
+            RecordFormat::Raw => Some(Box::new(BufWriter::new(File::create(&options.output)?))),
+            _ => None,
+        };
+
+        let recorder = Recorder { size: options.size, options, frames_seen: 0, frames_written: 0, frames: Vec::new(), raw };
+
+        return Ok(recorder);
+    }
+
+    // Counts one candidate frame, true if it should be left out.
+    pub fn skip(&mut self) -> bool {
+        let skip = self.frames_seen % self.options.every.max(1) != 0 || self.is_done();
+        self.frames_seen += 1;
+
+        return skip;
+    }
+
+    pub fn is_done(&self) -> bool {
+        return self.options.frames.is_some_and(|frames| self.frames_written >= frames);
+    }
+
+    // Takes one frame of RGBA8 rows from the top, scaled to the recording size.
+    // Returns whether the recording has all its frames.
+    pub fn push_frame(&mut self, rgba: &Vec<u8>, width: u32, height: u32) -> GameResult<bool> {
+        if self.is_done() { return Ok(true); }
+
+        let size = *self.size.get_or_insert([width, height]);
+        let frame = if size == [width, height] { rgba.clone() } else { resize_rgba(rgba, [width, height], size) };
+
+        match self.options.format {
+            RecordFormat::PngSequence => write_png(&self.options.output.join(format!("frame_{:06}.png", self.frames_written)), &frame, size)?,
+            RecordFormat::Apng => self.frames.push(frame),
+            RecordFormat::Raw => if let Some(raw) = &mut self.raw { raw.write_all(&frame)?; },
+        }
+
+        self.frames_written += 1;
+
+        return Ok(self.is_done());
+    }
+
+    // Writes out the APNG and flushes raw frames.
+    pub fn finish(&mut self) -> GameResult {
+        if let Some(raw) = &mut self.raw { raw.flush()?; }
+
+        if self.options.format == RecordFormat::Apng && !self.frames.is_empty() {
+            let size = self.size.unwrap_or([1, 1]);
+            write_apng(&self.options.output, &self.frames, size, self.options.fps)?;
+            self.frames.clear();
+        }
+
+        return Ok(());
+    }
+}
+
+// Nearest neighbour scaling of RGBA8 rows.
+pub fn resize_rgba(rgba: &Vec<u8>, size: [u32; 2], new_size: [u32; 2]) -> Vec<u8> {
+    let mut resized = Vec::with_capacity((new_size[0] * new_size[1] * 4) as usize);
+
+    for y in 0..new_size[1] {
+        let source_y = ((y as u64 * size[1] as u64) / new_size[1] as u64) as u32;
+
+        for x in 0..new_size[0] {
+            let source_x = ((x as u64 * size[0] as u64) / new_size[0] as u64) as u32;
+            let index = ((source_y * size[0] + source_x) * 4) as usize;
+
+            resized.extend_from_slice(&rgba[index..index + 4]);
+        }
+    }
+
+    return resized;
+}
+
+pub fn write_png(path: &Path, rgba: &Vec<u8>, size: [u32; 2]) -> GameResult {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|e| GameError::CustomError(format!("Failed to write {}: {}", path.display(), e)))?;
+
+    return Ok(());
+}
+
+// Loops forever at `fps` frames per second.
+pub fn write_apng(path: &Path, frames: &Vec<Vec<u8>>, size: [u32; 2], fps: u32) -> GameResult {
+    let error = |e: png::EncodingError| GameError::CustomError(format!("Failed to write {}: {}", path.display(), e));
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0).map_err(error)?;
+    encoder.set_frame_delay(1, fps.max(1) as u16).map_err(error)?;
+
+    let mut writer = encoder.write_header().map_err(error)?;
+    for frame in frames.iter() {
+        writer.write_image_data(frame).map_err(error)?;
+    }
+    writer.finish().map_err(error)?;
+
+    return Ok(());
+}
@@ -0,0 +1,156 @@
+use std::{fs::{self, File}, io::BufWriter, path::{Path, PathBuf}};
+use ggez::{GameResult, GameError};
+use ron::{from_str, ser::{to_string_pretty, PrettyConfig}};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{load_from, write_config_dir, ConfigRoot, SimulationConfig, SpeciesConfig, RenderConfig, WindowConfig, Pattern};
+
+
+// Keywords of the PNG text chunks a screenshot carries. Species use
+// `physarum:species:<index>`.
+pub const SIMULATION_KEYWORD: &str = "physarum:simulation";
+pub const SPECIES_KEYWORD: &str = "physarum:species";
+pub const RENDER_KEYWORD: &str = "physarum:render";
+pub const SEED_KEYWORD: &str = "physarum:seed";
+pub const FRAME_KEYWORD: &str = "physarum:frame";
+
+// Everything needed to get back to a screenshot, stored in it as UTF-8 text
+// chunks of RON so any PNG tool can show it.
+#[derive(Clone, Debug)]
+pub struct Provenance {
+    pub simulation_config: SimulationConfig,
+    pub species_configs: Vec<SpeciesConfig>,
+    pub render_config: RenderConfig,
+    // Also in `simulation_config`, kept on its own so it reads at a glance.
+    pub seed: u64,
+    // Simulation steps run when the frame was drawn.
+    pub frame: usize,
+}
+
+impl Provenance {
+    pub fn new(simulation_config: &SimulationConfig, species_configs: &Vec<SpeciesConfig>, render_config: &RenderConfig, frame: usize) -> GameResult<Provenance> {
+        let provenance = Provenance {
+            simulation_config: simulation_config.clone(),
+            species_configs: species_configs.clone(),
+            render_config: render_config.clone(),
+            seed: simulation_config.seed,
+            frame,
+        };
+
+        return Ok(provenance);
+    }
+
+    pub fn to_text_chunks(&self) -> GameResult<Vec<(String, String)>> {
+        let mut chunks = vec![
+            (SIMULATION_KEYWORD.to_string(), encode(&self.simulation_config)?),
+            (RENDER_KEYWORD.to_string(), encode(&self.render_config)?),
+            (SEED_KEYWORD.to_string(), self.seed.to_string()),
+            (FRAME_KEYWORD.to_string(), self.frame.to_string()),
+        ];
+
+        for (index, species_config) in self.species_configs.iter().enumerate() {
+            chunks.push((format!("{}:{}", SPECIES_KEYWORD, index), encode(species_config)?));
+        }
+
+        return Ok(chunks);
+    }
+
+    pub fn from_text_chunks(chunks: &Vec<(String, String)>) -> GameResult<Provenance> {
+        let find = |keyword: &str| chunks.iter().find(|(key, _)| key == keyword).map(|(_, text)| text.as_str());
+        let missing = |keyword: &str| GameError::ResourceLoadError(format!("Screenshot has no {} text chunk", keyword));
+
+        let simulation_config: SimulationConfig = decode(SIMULATION_KEYWORD, find(SIMULATION_KEYWORD).ok_or_else(|| missing(SIMULATION_KEYWORD))?)?;
+        let render_config: RenderConfig = decode(RENDER_KEYWORD, find(RENDER_KEYWORD).ok_or_else(|| missing(RENDER_KEYWORD))?)?;
+        let seed = match find(SEED_KEYWORD) {
+            Some(text) => text.trim().parse().map_err(|_| GameError::ResourceLoadError(format!("Screenshot seed {} is not a number", text)))?,
+            None => simulation_config.seed,
+        };
+        let frame = find(FRAME_KEYWORD).ok_or_else(|| missing(FRAME_KEYWORD))?.trim().parse()
+            .map_err(|_| GameError::ResourceLoadError(String::from("Screenshot frame is not a number")))?;
+
+        let mut species_configs = Vec::new();
+        while let Some(text) = find(&format!("{}:{}", SPECIES_KEYWORD, species_configs.len())) {
+            species_configs.push(decode(SPECIES_KEYWORD, text)?);
+        }
+        if species_configs.is_empty() { return Err(missing(SPECIES_KEYWORD)); }
+
+        // The seed chunk wins, so a hand edited seed is what gets restored.
+        let simulation_config = SimulationConfig { seed, ..simulation_config };
+
+        let provenance = Provenance { simulation_config, species_configs, render_config, seed, frame };
+
+        return Ok(provenance);
+    }
+}
+
+fn encode<T: Serialize>(config: &T) -> GameResult<String> {
+    return to_string_pretty(config, PrettyConfig::new().struct_names(true)).map_err(|e| GameError::CustomError(format!("Failed to encode screenshot metadata: {}", e)));
+}
+
+fn decode<T: DeserializeOwned>(keyword: &str, text: &str) -> GameResult<T> {
+    return from_str(text).map_err(|e| GameError::ResourceLoadError(format!("Failed to read {} from screenshot: {}", keyword, e)));
+}
+
+pub fn save_screenshot(path: &Path, rgba: &Vec<u8>, size: [u32; 2], provenance: &Provenance) -> GameResult {
+    let error = |e: png::EncodingError| GameError::CustomError(format!("Failed to write {}: {}", path.display(), e));
+
+    if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    for (keyword, text) in provenance.to_text_chunks()? {
+        encoder.add_itxt_chunk(keyword, text).map_err(error)?;
+    }
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(error)?;
+
+    return Ok(());
+}
+
+pub fn load_provenance(path: &Path) -> GameResult<Provenance> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let reader = decoder.read_info().map_err(|e| GameError::ResourceLoadError(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let mut chunks = Vec::new();
+    for chunk in reader.info().utf8_text.iter() {
+        let text = chunk.get_text().map_err(|e| GameError::ResourceLoadError(format!("Failed to read {}: {}", path.display(), e)))?;
+        chunks.push((chunk.keyword.clone(), text));
+    }
+
+    return Provenance::from_text_chunks(&chunks);
+}
+
+// Finds `physarum restore SCREENSHOT [DIR]` among the command line arguments,
+// DIR defaulting to `restored/<screenshot name>`.
+pub fn restore_args(args: &[String]) -> GameResult<Option<(PathBuf, PathBuf)>> {
+    if !args.get(1).is_some_and(|arg| arg == "restore") { return Ok(None); }
+
+    let screenshot = args.get(2).map(PathBuf::from).ok_or_else(|| GameError::CustomError(String::from("Usage: physarum restore SCREENSHOT [DIR]")))?;
+    let dir = match args.get(3).filter(|arg| !arg.starts_with("--")) {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new("restored").join(screenshot.file_stem().unwrap_or_default()),
+    };
+
+    return Ok(Some((screenshot, dir)));
+}
+
+// Writes the screenshot's configs into `dir`, ready for `--config dir`. The
+// window config and any mask image come from `root`.
+pub fn restore_screenshot(root: &ConfigRoot, screenshot: &Path, dir: &Path) -> GameResult {
+    let provenance = load_provenance(screenshot)?;
+    let window_config = load_from::<WindowConfig>(root, "window")?;
+
+    write_config_dir(dir, &provenance.simulation_config, &window_config, &provenance.render_config, &provenance.species_configs)?;
+
+    if let Pattern::Image(name) = &provenance.simulation_config.pattern {
+        // Validation points out a missing mask, the rest is still worth restoring.
+        if let Some(path) = root.path(name).ok().filter(|path| path.is_file()) {
+            fs::copy(path, dir.join(name))?;
+        }
+    }
+
+    return Ok(());
+}
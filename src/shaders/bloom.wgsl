@@ -0,0 +1,86 @@
+struct PostSettings {
+    tone_map: u32;
+    auto_exposure: u32;
+    exposure_scale: f32;
+    key: f32;
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    adaptation_rate: f32;
+    delta_time: f32;
+    bloom: u32;
+    bloom_threshold: f32;
+    bloom_intensity: f32;
+    bloom_radius: u32;
+    vignette: u32;
+    vignette_strength: f32;
+    vignette_radius: f32;
+    vignette_softness: f32;
+};
+
+
+[[group(0), binding(0)]] var<uniform> settings: PostSettings;
+[[group(0), binding(1)]] var source: texture_2d<f32>;
+[[group(0), binding(2)]] var destination: texture_storage_2d<rgba16float, write>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Each texel of the half resolution texture averages 2x2 HDR pixels and keeps
+// only the light above the threshold. Must match `bloom_image` in `post.rs`.
+[[stage(compute), workgroup_size(8, 8)]]
+fn bright_pass([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let size = textureDimensions(destination);
+    let position = vec2<i32>(global_id.xy);
+
+    if (position.x >= size.x || position.y >= size.y) {
+        return;
+    }
+
+    let source_size = textureDimensions(source);
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+
+    for (var i = 0; i < 4; i = i + 1) {
+        let pixel = min(position * 2 + vec2<i32>(i % 2, i / 2), source_size - vec2<i32>(1, 1));
+        color = color + textureLoad(source, pixel, 0).rgb * 0.25;
+    }
+
+    let l = luminance(color);
+    let scale = max(l - settings.bloom_threshold, 0.0) / max(l, 0.00001);
+
+    textureStore(destination, position, vec4<f32>(color * scale, 1.0));
+}
+
+// Must match `bloom_weights` and `blur` in `post.rs`.
+fn blur(position: vec2<i32>, direction: vec2<i32>) {
+    let size = textureDimensions(destination);
+
+    if (position.x >= size.x || position.y >= size.y) {
+        return;
+    }
+
+    let radius = i32(min(settings.bloom_radius, 32u));
+    let sigma = max(f32(radius) / 2.0, 0.5);
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+    var total = 0.0;
+
+    for (var i = -radius; i <= radius; i = i + 1) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        let pixel = clamp(position + direction * i, vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+
+        color = color + textureLoad(source, pixel, 0).rgb * weight;
+        total = total + weight;
+    }
+
+    textureStore(destination, position, vec4<f32>(color / total, 1.0));
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn blur_horizontal([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    blur(vec2<i32>(global_id.xy), vec2<i32>(1, 0));
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn blur_vertical([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    blur(vec2<i32>(global_id.xy), vec2<i32>(0, 1));
+}
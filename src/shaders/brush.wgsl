@@ -0,0 +1,166 @@
+struct Constants {
+    height: f32;
+    width: f32;
+    diffusion_rate: f32;
+    boundary: u32;
+    seed: u32;
+    decay_rate: f32;
+    diffusion_kernel: u32;
+    diffusion_radius: u32;
+};
+
+struct Brush {
+    position: vec2<f32>;
+    radius: f32;
+    strength: f32;
+    tool: u32;
+    species: u32;
+    spawn_start: u32;
+    spawn_count: u32;
+    seed: u32;
+    padding_0: u32;
+    padding_1: u32;
+    padding_2: u32;
+};
+
+struct Agent {
+    position: vec2<f32>;
+    angle: f32;
+    species: u32;
+};
+
+struct Agents {
+    agents: array<Agent>;
+};
+
+struct Trail {
+    position: vec2<f32>;
+    species: u32;
+    padding: u32;
+    value: vec4<f32>;
+};
+
+struct Map {
+    trail: array<Trail>;
+};
+
+
+[[group(0), binding(0)]] var<uniform> constants: Constants;
+[[group(0), binding(1)]] var<uniform> brush: Brush;
+[[group(0), binding(2)]] var<storage, read_write> agent_src: Agents;
+[[group(0), binding(3)]] var<storage, read_write> map: Map;
+
+fn hash(state: u32) -> u32 {
+    var res = state;
+
+    res = res ^ 2747636419u;
+    res = res * 2654435769u;
+    res = res ^ (res >> 16u);
+    res = res * 2654435769u;
+    res = res ^ (res >> 16u);
+    res = res * 2654435769u;
+
+    return res;
+}
+
+fn scale_to_range_01(state: u32) -> f32 {
+    return f32(state) / 4294967295.0;
+}
+
+// Must match `brush_offset` in `brush.rs`.
+fn brush_offset(position: vec2<f32>) -> vec2<f32> {
+    let aspect = constants.width / constants.height;
+    let offset = position - brush.position;
+
+    return vec2<f32>(offset.x, offset.y / aspect);
+}
+
+// Must match `brush_falloff` in `brush.rs`.
+fn brush_falloff(distance: f32) -> f32 {
+    if (distance >= brush.radius) {
+        return 0.0;
+    }
+
+    let t = 1.0 - distance / brush.radius;
+
+    return t * t;
+}
+
+// Paint and Erase, one invocation per trail cell of the current map.
+[[stage(compute), workgroup_size(32)]]
+fn brush_map([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let index = global_id.x;
+
+    if (index >= arrayLength(&map.trail)) {
+        return;
+    }
+
+    var trail = map.trail[index];
+    let cell_size = vec2<f32>(2.0 / constants.width, 2.0 / constants.height);
+    let amount = brush.strength * brush_falloff(length(brush_offset(trail.position + cell_size / 2.0)));
+
+    if (amount <= 0.0) {
+        return;
+    }
+
+    // Paint
+    if (brush.tool == 0u) {
+        trail.value[brush.species % 4u] = trail.value[brush.species % 4u] + amount;
+    }
+    // Erase
+    else {
+        trail.value = trail.value * (1.0 - min(amount, 1.0));
+    }
+
+    map.trail[index] = trail;
+}
+
+// Spawn and Push, one invocation per agent.
+[[stage(compute), workgroup_size(32)]]
+fn brush_agents([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let index = global_id.x;
+    let agent_count = arrayLength(&agent_src.agents);
+
+    if (index >= agent_count) {
+        return;
+    }
+
+    let TAU = 6.28318530717958647692528676655900577;
+    let aspect = constants.width / constants.height;
+
+    var agent = agent_src.agents[index];
+
+    // Spawn
+    if (brush.tool == 2u) {
+        if ((index + agent_count - brush.spawn_start % agent_count) % agent_count >= brush.spawn_count) {
+            return;
+        }
+
+        var random = hash(hash(brush.seed + index));
+        let distance = brush.radius * sqrt(scale_to_range_01(random));
+        random = hash(random);
+        let theta = scale_to_range_01(random) * TAU;
+        random = hash(random);
+
+        agent.position = brush.position + vec2<f32>(cos(theta), sin(theta) * aspect) * distance;
+        agent.angle = scale_to_range_01(random) * TAU;
+        agent.species = brush.species;
+    }
+    // Push, agents pushed past the edge are brought back by the next agent step.
+    else {
+        let offset = brush_offset(agent.position);
+        let distance = length(offset);
+        let amount = brush.strength * brush_falloff(distance) * brush.radius;
+
+        if (amount <= 0.0 || distance <= 0.0) {
+            return;
+        }
+
+        let direction = offset / distance;
+
+        agent.position = agent.position + vec2<f32>(direction.x, direction.y * aspect) * amount;
+        agent.angle = atan2(direction.y, direction.x);
+    }
+
+    agent_src.agents[index] = agent;
+}
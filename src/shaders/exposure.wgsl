@@ -0,0 +1,90 @@
+struct PostSettings {
+    tone_map: u32;
+    auto_exposure: u32;
+    exposure_scale: f32;
+    key: f32;
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    adaptation_rate: f32;
+    delta_time: f32;
+    bloom: u32;
+    bloom_threshold: f32;
+    bloom_intensity: f32;
+    bloom_radius: u32;
+    vignette: u32;
+    vignette_strength: f32;
+    vignette_radius: f32;
+    vignette_softness: f32;
+};
+
+// `luminance` is the adapted average carried between frames, 0 before the
+// first measurement. `exposure` is read by `post.wgsl`.
+struct Exposure {
+    bins: array<atomic<u32>, 64>;
+    luminance: f32;
+    exposure: f32;
+};
+
+
+[[group(0), binding(0)]] var<uniform> settings: PostSettings;
+[[group(0), binding(1)]] var hdr: texture_2d<f32>;
+[[group(0), binding(2)]] var<storage, read_write> exposure: Exposure;
+
+// Counts every lit pixel into a bin of log2 luminance. Must match
+// `histogram_bin` in `post.rs`.
+[[stage(compute), workgroup_size(8, 8)]]
+fn histogram([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let size = textureDimensions(hdr);
+    let position = vec2<i32>(global_id.xy);
+
+    if (position.x >= size.x || position.y >= size.y) {
+        return;
+    }
+
+    let l = dot(textureLoad(hdr, position, 0).rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+
+    if (l < 0.00001) {
+        return;
+    }
+
+    let t = clamp((log2(l) - settings.min_log_luminance) / settings.log_luminance_range, 0.0, 1.0);
+
+    atomicAdd(&exposure.bins[min(u32(t * 64.0), 63u)], 1u);
+}
+
+// One invocation averages the histogram, adapts towards it and clears the
+// bins for the next frame. Must match `histogram_luminance`, `adapt` and
+// `exposure` in `post.rs`.
+[[stage(compute), workgroup_size(1)]]
+fn average() {
+    var count = 0u;
+    var weighted = 0.0;
+
+    for (var i = 0u; i < 64u; i = i + 1u) {
+        let bin = atomicLoad(&exposure.bins[i]);
+
+        count = count + bin;
+        weighted = weighted + (f32(i) + 0.5) * f32(bin);
+        atomicStore(&exposure.bins[i], 0u);
+    }
+
+    var luminance = exposure.luminance;
+
+    if (count > 0u) {
+        let measured = exp2(weighted / f32(count) / 64.0 * settings.log_luminance_range + settings.min_log_luminance);
+
+        if (luminance <= 0.0 || settings.adaptation_rate <= 0.0) {
+            luminance = measured;
+        } else {
+            luminance = luminance + (measured - luminance) * (1.0 - exp(-settings.delta_time * settings.adaptation_rate));
+        }
+    }
+
+    exposure.luminance = luminance;
+
+    if (settings.auto_exposure != 0u && luminance > 0.0) {
+        exposure.exposure = settings.key / max(luminance, 0.00001) * settings.exposure_scale;
+    } else {
+        exposure.exposure = settings.exposure_scale;
+    }
+}
@@ -0,0 +1,40 @@
+struct Constants {
+    height: f32;
+    width: f32;
+    diffusion_rate: f32;
+    boundary: u32;
+    seed: u32;
+    decay_rate: f32;
+    diffusion_kernel: u32;
+    diffusion_radius: u32;
+};
+
+struct Trail {
+    position: vec2<f32>;
+    species: u32;
+    padding: u32;
+    value: vec4<f32>;
+};
+
+struct Map {
+    trail: array<Trail>;
+};
+
+
+[[group(0), binding(0)]] var<uniform> constants: Constants;
+[[group(0), binding(1)]] var<storage, read> map: Map;
+[[group(0), binding(2)]] var map_texture: texture_storage_2d<rgba16float, write>;
+
+// Texel (x, y) holds cell (x, y), so row 0 of the texture is the bottom of the map.
+[[stage(compute), workgroup_size(32)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let index = global_id.x;
+
+    if (index >= arrayLength(&map.trail)) {
+        return;
+    }
+
+    let width = u32(constants.width);
+
+    textureStore(map_texture, vec2<i32>(i32(index % width), i32(index / width)), map.trail[index].value);
+}
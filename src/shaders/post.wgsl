@@ -0,0 +1,112 @@
+struct ScreenOutput {
+    [[builtin(position)]] pos: vec4<f32>;
+};
+
+struct PostSettings {
+    tone_map: u32;
+    auto_exposure: u32;
+    exposure_scale: f32;
+    key: f32;
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    adaptation_rate: f32;
+    delta_time: f32;
+    bloom: u32;
+    bloom_threshold: f32;
+    bloom_intensity: f32;
+    bloom_radius: u32;
+    vignette: u32;
+    vignette_strength: f32;
+    vignette_radius: f32;
+    vignette_softness: f32;
+};
+
+struct Exposure {
+    bins: array<u32, 64>;
+    luminance: f32;
+    exposure: f32;
+};
+
+
+[[group(0), binding(0)]] var<uniform> settings: PostSettings;
+[[group(0), binding(1)]] var hdr: texture_2d<f32>;
+[[group(0), binding(2)]] var bloom: texture_2d<f32>;
+[[group(0), binding(3)]] var bloom_sampler: sampler;
+[[group(0), binding(4)]] var<storage, read> exposure: Exposure;
+
+// Must match `hable` in `post.rs`.
+fn hable(x: f32) -> f32 {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+
+    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
+
+// Must match `tone_map` in `post.rs`.
+fn tone_map_channel(value: f32) -> f32 {
+    let x = max(value, 0.0);
+
+    // Reinhard
+    if (settings.tone_map == 1u) {
+        return x / (1.0 + x);
+    }
+    // ACES
+    if (settings.tone_map == 2u) {
+        return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+    }
+    // Filmic
+    if (settings.tone_map == 3u) {
+        return min(hable(x * 2.0) / hable(11.2), 1.0);
+    }
+
+    return min(x, 1.0);
+}
+
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(tone_map_channel(color.r), tone_map_channel(color.g), tone_map_channel(color.b));
+}
+
+// Must match `vignette` in `post.rs`.
+fn vignette(uv: vec2<f32>) -> f32 {
+    if (settings.vignette == 0u) {
+        return 1.0;
+    }
+
+    let distance = length(uv - vec2<f32>(0.5, 0.5)) * 1.41421356237;
+    let t = clamp((distance - settings.vignette_radius) / settings.vignette_softness, 0.0, 1.0);
+
+    return 1.0 - settings.vignette_strength * t * t * (3.0 - 2.0 * t);
+}
+
+// One triangle large enough to cover the window.
+[[stage(vertex)]]
+fn main_vs([[builtin(vertex_index)]] vertex_index: u32) -> ScreenOutput {
+    var screen_output: ScreenOutput;
+
+    let view = vec2<f32>(f32(vertex_index & 1u) * 4.0 - 1.0, f32(vertex_index >> 1u) * 4.0 - 1.0);
+
+    screen_output.pos = vec4<f32>(view, 0.0, 1.0);
+
+    return screen_output;
+}
+
+// Adds the bloom, exposes, tone maps and darkens the corners, in that order.
+// Must match `post_process` in `post.rs`.
+[[stage(fragment)]]
+fn main_fs(screen_output: ScreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(hdr);
+    let pixel = vec2<i32>(screen_output.pos.xy);
+    let uv = screen_output.pos.xy / vec2<f32>(size);
+
+    var color = textureLoad(hdr, pixel, 0).rgb;
+
+    if (settings.bloom != 0u) {
+        color = color + textureSampleLevel(bloom, bloom_sampler, uv, 0.0).rgb * settings.bloom_intensity;
+    }
+
+    return vec4<f32>(tone_map(color * exposure.exposure) * vignette(uv), 1.0);
+}
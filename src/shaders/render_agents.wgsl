@@ -1,11 +1,145 @@
+struct AgentOutput {
+    [[builtin(position)]] pos: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+};
+
+struct Agent {
+    position: vec2<f32>;
+    angle: f32;
+    species: u32;
+};
+
+struct Agents {
+    agents: array<Agent>;
+};
+
+struct Species {
+    sensor_size: f32;
+    sensor_angle: f32;
+    sensor_distance: f32;
+    turn_speed: f32;
+    move_speed: f32;
+    forward_bias: f32;
+    left_bias: f32;
+    right_bias: f32;
+    weight: f32;
+    color_r: f32;
+    color_g: f32;
+    color_b: f32;
+    interaction: vec4<f32>;
+    sensor_kernel: u32;
+};
+
+struct SpeciesMap {
+    species: array<Species>;
+};
+
+struct Camera {
+    offset: vec2<f32>;
+    zoom: f32;
+    tiled: u32;
+    viewport: vec2<f32>;
+    padding: vec2<f32>;
+};
+
+struct AgentStyle {
+    shape: u32;
+    coloring: u32;
+    subsample: u32;
+    size: f32;
+    pixel_size: vec2<f32>;
+    padding: vec2<f32>;
+};
+
+
+[[group(0), binding(0)]] var<storage, read> agent_src: Agents;
+[[group(0), binding(1)]] var<storage, read> species_map: SpeciesMap;
+[[group(0), binding(2)]] var<uniform> camera: Camera;
+[[group(0), binding(3)]] var<uniform> style: AgentStyle;
+
+// Must match `Camera::scale` in `camera.rs`.
+fn view_scale() -> vec2<f32> {
+    var zoom = camera.zoom;
+
+    if (camera.tiled != 0u) {
+        zoom = zoom / 3.0;
+    }
+
+    return camera.viewport * zoom;
+}
+
+// Must match `heading_color` in `palette.rs`.
+fn heading_color(angle: f32) -> vec3<f32> {
+    let TAU = 6.28318530717958647692528676655900577;
+    let hue = fract(angle / TAU) * 6.0;
+    let k = (vec3<f32>(5.0, 3.0, 1.0) + vec3<f32>(hue, hue, hue)) % vec3<f32>(6.0, 6.0, 6.0);
+
+    return vec3<f32>(1.0, 1.0, 1.0) - clamp(min(k, vec3<f32>(4.0, 4.0, 4.0) - k), vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+}
+
+// Must match `agent_color` in `palette.rs`.
+fn agent_color(agent: Agent) -> vec3<f32> {
+    if (style.coloring == 1u) {
+        return heading_color(agent.angle);
+    }
+
+    if (agent.species >= arrayLength(&species_map.species)) {
+        return vec3<f32>(1.0, 1.0, 1.0);
+    }
+
+    let species = species_map.species[agent.species];
+
+    return vec3<f32>(species.color_r, species.color_g, species.color_b);
+}
+
+// One instance per drawn agent, six vertices per tile of the tiled view.
 [[stage(vertex)]]
 fn main_vs(
-    [[location(0)]] pos: vec2<f32>
-) -> [[builtin(position)]] vec4<f32> {
-    return vec4<f32>(pos.x, pos.y, 0.0, 1.0);
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[builtin(instance_index)]] instance_index: u32,
+) -> AgentOutput {
+    var agent_output: AgentOutput;
+
+    let index = min(instance_index * style.subsample, arrayLength(&agent_src.agents) - 1u);
+    let agent = agent_src.agents[index];
+    let corner_index = vertex_index % 6u;
+    let tile = vertex_index / 6u;
+
+    // Corner in pixels around the agent.
+    var local = vec2<f32>(0.0, 0.0);
+
+    // Sprite, the last three vertices collapse onto one point.
+    if (style.shape == 1u) {
+        var triangle = array<vec2<f32>, 3>(vec2<f32>(1.0, 0.0), vec2<f32>(-0.6, 0.5), vec2<f32>(-0.6, -0.5));
+        let corner = triangle[min(corner_index, 2u)];
+        let c = cos(agent.angle);
+        let s = sin(agent.angle);
+
+        local = vec2<f32>(corner.x * c - corner.y * s, corner.x * s + corner.y * c) * style.size;
+    }
+    // Point
+    else {
+        var corners = array<u32, 6>(0u, 1u, 2u, 2u, 1u, 3u);
+        let corner = corners[corner_index];
+
+        local = (vec2<f32>(f32(corner & 1u), f32(corner >> 1u)) - vec2<f32>(0.5, 0.5)) * style.size;
+    }
+
+    var position = agent.position;
+
+    if (camera.tiled != 0u) {
+        position = position + vec2<f32>(f32(tile % 3u) - 1.0, f32(tile / 3u) - 1.0) * 2.0;
+    }
+
+    let view = (position - camera.offset) * view_scale() + local * style.pixel_size;
+
+    agent_output.pos = vec4<f32>(view.x, view.y, 0.0, 1.0);
+    agent_output.color = vec4<f32>(agent_color(agent), 1.0);
+
+    return agent_output;
 }
 
 [[stage(fragment)]]
-fn main_fs() -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+fn main_fs(agent_output: AgentOutput) -> [[location(0)]] vec4<f32> {
+    return agent_output.color;
 }
@@ -1,7 +1,7 @@
-struct TrailOutput {
+struct ScreenOutput {
     [[builtin(position)]] pos: vec4<f32>;
-    [[location(0)]] value: f32;
-    [[location(10)]] species: u32;
+    // Clip space position, x and y in [-1, 1] across the window.
+    [[location(0)]] view: vec2<f32>;
 };
 
 struct Species {
@@ -17,59 +17,167 @@ struct Species {
     color_r: f32;
     color_g: f32;
     color_b: f32;
+    interaction: vec4<f32>;
+    sensor_kernel: u32;
 };
 
 struct SpeciesMap {
     species: array<Species>;
 };
 
+struct Palette {
+    exposure: f32;
+    gamma: f32;
+    color_map: u32;
+    blend: u32;
+    stop_count: u32;
+    filter: u32;
+    padding_0: u32;
+    padding_1: u32;
+    stops: array<vec4<f32>, 16>;
+};
+
+struct Camera {
+    offset: vec2<f32>;
+    zoom: f32;
+    tiled: u32;
+    viewport: vec2<f32>;
+    padding: vec2<f32>;
+};
+
 
 [[group(0), binding(0)]] var<storage, read> species_map: SpeciesMap;
+[[group(0), binding(1)]] var<uniform> camera: Camera;
+[[group(0), binding(2)]] var<uniform> palette: Palette;
+[[group(0), binding(3)]] var map_texture: texture_2d<f32>;
+[[group(0), binding(4)]] var nearest_sampler: sampler;
+[[group(0), binding(5)]] var linear_sampler: sampler;
+
+// Must match `Camera::scale` in `camera.rs`.
+fn view_scale() -> vec2<f32> {
+    var zoom = camera.zoom;
 
+    if (camera.tiled != 0u) {
+        zoom = zoom / 3.0;
+    }
+
+    return camera.viewport * zoom;
+}
+
+// One triangle large enough to cover the window.
 [[stage(vertex)]]
-fn main_vs(
-    [[location(0)]] pos_x: f32,
-    [[location(1)]] pos_y: f32,
-    [[location(2)]] value: f32,
-    [[location(3)]] species: u32,
-) -> TrailOutput {
-    var trail_output: TrailOutput;
-
-    trail_output.pos = vec4<f32>(pos_x, pos_y, 0.0, 1.0);
-    trail_output.value = value;
-    trail_output.species = species;
-
-    return trail_output;
+fn main_vs([[builtin(vertex_index)]] vertex_index: u32) -> ScreenOutput {
+    var screen_output: ScreenOutput;
+
+    let view = vec2<f32>(f32(vertex_index & 1u) * 4.0 - 1.0, f32(vertex_index >> 1u) * 4.0 - 1.0);
+
+    screen_output.pos = vec4<f32>(view, 0.0, 1.0);
+    screen_output.view = view;
+
+    return screen_output;
+}
+
+// Trail values at a window position, zero outside the map or outside the 3x3
+// tiles of the tiled view. Must match `Camera::view_to_map` in `camera.rs`.
+fn sample_map(view: vec2<f32>) -> vec4<f32> {
+    let position = view / view_scale() + camera.offset;
+
+    var extent = 1.0;
+    if (camera.tiled != 0u) {
+        extent = 3.0;
+    }
+
+    if (abs(position.x) > extent || abs(position.y) > extent) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+
+    let uv = (position + vec2<f32>(1.0, 1.0)) * 0.5;
+
+    if (palette.filter == 1u) {
+        return textureSampleLevel(map_texture, linear_sampler, uv, 0.0);
+    }
+
+    return textureSampleLevel(map_texture, nearest_sampler, uv, 0.0);
 }
 
+// Must match `sample_gradient` in `palette.rs`.
+fn sample_gradient(value: f32) -> vec3<f32> {
+    let count = min(palette.stop_count, 16u);
+
+    if (count == 0u) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+
+    let t = clamp(value, 0.0, 1.0);
+
+    if (t <= palette.stops[0].w) {
+        return palette.stops[0].rgb;
+    }
+
+    for (var i = 1u; i < count; i = i + 1u) {
+        let a = palette.stops[i - 1u];
+        let b = palette.stops[i];
+
+        if (t <= b.w) {
+            var f = 1.0;
+            if (b.w > a.w) {
+                f = (t - a.w) / (b.w - a.w);
+            }
+
+            return mix(a.rgb, b.rgb, vec3<f32>(f, f, f));
+        }
+    }
+
+    return palette.stops[count - 1u].rgb;
+}
+
+// Must match `shade` in `palette.rs`.
 [[stage(fragment)]]
-fn main_fs(trail_output: TrailOutput) -> [[location(0)]] vec4<f32> {
-    var v = trail_output.value;
-    let species = species_map.species[trail_output.species];
-
-    return vec4<f32>(species.color_r * v, species.color_g * v, species.color_b * v, 1.0);
-
-    // return vec4<f32>(0.0 * v, 0.0 * v, 1.0 * v, 1.0);
-
-    // var r = v;
-    // var g = 0.0;
-    // var b = 0.0;
-    
-    // var min = 0.33;
-    // var max = 0.66;
-
-    // if (v > min && v < max) {
-    //     r = min;
-    //     g = v - min;
-    // }
-    // else if (v > max) {
-    //     r = min;
-    //     g = min;
-    //     b = v - max;
-    //     r = min - b;
-    // }
-
-    // return vec4<f32>(g, b, r, 1.0);
-    // return vec4<f32>(b, r, g, 1.0);
-    // return vec4<f32>(b, g, r, 1.0);
+fn main_fs(screen_output: ScreenOutput) -> [[location(0)]] vec4<f32> {
+    let value = sample_map(screen_output.view);
+    let species_count = min(arrayLength(&species_map.species), 4u);
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+
+    if (palette.color_map == 0u) {
+        var dominant = 0u;
+
+        for (var i = 0u; i < species_count; i = i + 1u) {
+            let species = species_map.species[i];
+            let weighted = vec3<f32>(species.color_r, species.color_g, species.color_b) * value[i];
+
+            // Max
+            if (palette.blend == 1u) {
+                color = max(color, weighted);
+            }
+            // Dominant
+            else if (palette.blend == 2u) {
+                if (value[i] > value[dominant]) {
+                    dominant = i;
+                }
+            }
+            // Additive
+            else {
+                color = color + weighted;
+            }
+        }
+
+        if (palette.blend == 2u && species_count > 0u) {
+            let species = species_map.species[dominant];
+            color = vec3<f32>(species.color_r, species.color_g, species.color_b) * value[dominant];
+        }
+
+        color = color * palette.exposure;
+    } else {
+        var total = 0.0;
+
+        for (var i = 0u; i < species_count; i = i + 1u) {
+            total = total + value[i];
+        }
+
+        color = sample_gradient(total * palette.exposure);
+    }
+
+    let inverse_gamma = 1.0 / palette.gamma;
+
+    return vec4<f32>(pow(max(color, vec3<f32>(0.0, 0.0, 0.0)), vec3<f32>(inverse_gamma, inverse_gamma, inverse_gamma)), 1.0);
 }
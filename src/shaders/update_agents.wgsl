@@ -1,7 +1,12 @@
 struct Constants {
-    window_height: f32;
-    window_width: f32;
+    height: f32;
+    width: f32;
     diffusion_rate: f32;
+    boundary: u32;
+    seed: u32;
+    decay_rate: f32;
+    diffusion_kernel: u32;
+    diffusion_radius: u32;
 };
 
 struct Param {
@@ -22,6 +27,8 @@ struct Species {
     color_r: f32;
     color_g: f32;
     color_b: f32;
+    interaction: vec4<f32>;
+    sensor_kernel: u32;
 };
 
 struct SpeciesMap {
@@ -42,13 +49,20 @@ struct Agents {
 
 struct Trail {
     position: vec2<f32>;
-    value: f32;
+    species: u32;
+    padding: u32;
+    value: vec4<f32>;
 };
 
 struct Map {
     trail: array<Trail>;
 };
 
+// Fixed point deposits, `MAX_SPECIES` channels per cell.
+struct Deposits {
+    deposit: array<atomic<u32>>;
+};
+
 
 fn hash(state: u32) -> u32 {
     var res = state;
@@ -72,71 +86,142 @@ fn scale_to_range_01(state: u32) -> f32 {
 [[group(0), binding(2)]] var<storage, read> species_map: SpeciesMap;
 [[group(0), binding(3)]] var<storage, read_write> agent_src: Agents;
 [[group(0), binding(4)]] var<storage, read> map_src: Map;
-[[group(0), binding(5)]] var<storage, read_write> map_dst: Map;
-
-fn get_cell_index(p: vec2<f32>) -> i32 {
-    var pos = p;
-
-    if (pos.x >= 1.0) {
-        pos.x = -1.0;
+[[group(0), binding(5)]] var<storage, read> map_dst: Map;
+[[group(0), binding(6)]] var<storage, read_write> deposits: Deposits;
+
+// Must match `DEPOSIT_SCALE` in `cpu_simulation.rs`.
+let DEPOSIT_SCALE: f32 = 65536.0;
+
+// Maps a cell coordinate outside the map back inside according to the boundary
+// mode. Returns -1 for cells that are absorbed. Must match `who_cell` in
+// `cpu_simulation.rs`.
+fn boundary_index(p: i32, size: i32) -> i32 {
+    // Reflect
+    if (constants.boundary == 1u) {
+        if (p < 0) {
+            return min(-p - 1, size - 1);
+        }
+        if (p >= size) {
+            return max(2 * size - p - 1, 0);
+        }
+        return p;
     }
-    else if (pos.x <= -1.0) {
-        pos.x = 1.0;
+    // Absorb
+    if (constants.boundary == 2u) {
+        if (p < 0 || p >= size) {
+            return -1;
+        }
+        return p;
     }
-
-    if (pos.y >= 1.0) {
-        pos.y = -1.0;
+    // Clamp
+    if (constants.boundary == 3u) {
+        return clamp(p, 0, size - 1);
     }
-    else if (pos.y <= -1.0) {
-        pos.y = 1.0;
+
+    return ((p % size) + size) % size;
+}
+
+fn who_cell(pos: vec2<i32>) -> i32 {
+    let width = i32(constants.width);
+    let height = i32(constants.height);
+
+    let x = boundary_index(pos.x, width);
+    let y = boundary_index(pos.y, height);
+
+    if (x < 0 || y < 0) {
+        return -1;
     }
 
-    let size = constants.window_width;
-    let half = size / 2.0;
+    return (y * width) + x;
+}
+
+fn get_cell_index(p: vec2<f32>) -> i32 {
+    let size = vec2<f32>(constants.width, constants.height);
+    let cell = floor((p + vec2<f32>(1.0, 1.0)) / 2.0 * size);
+
+    return who_cell(vec2<i32>(cell));
+}
 
-    var pos_x = (pos.x + 1.0) / 2.0 * size;
-    var pos_y = (pos.y + 1.0) / 2.0 * size;
+// Must match `kernel_weight` in `kernel.rs`.
+fn kernel_weight(kernel: u32, offset: vec2<i32>, radius: i32) -> f32 {
+    let distance_squared = f32(offset.x * offset.x + offset.y * offset.y);
 
-    let rounded_x = floor(pos_x);
-    let rounded_y = floor(pos_y);
+    // Disc
+    if (kernel == 1u) {
+        if (distance_squared <= f32(radius * radius)) {
+            return 1.0;
+        }
+        return 0.0;
+    }
+    // Gaussian
+    if (kernel == 2u) {
+        let sigma = max(f32(radius) / 2.0, 0.5);
+        return exp(-distance_squared / (2.0 * sigma * sigma));
+    }
 
-    let index = i32((size * rounded_y) + rounded_x);
+    return 1.0;
+}
 
-    return index;
+// Distances are measured in units of the map width, so directions are
+// stretched along y to stay isotropic on non-square maps.
+fn aspect_scale() -> vec2<f32> {
+    return vec2<f32>(1.0, constants.width / constants.height);
 }
 
-fn sense(agent: Agent, sensor_size: f32, sensor_distance: f32, sensor_angle_offset: f32) -> f32 {
-    let width = constants.window_width;
-    let height = constants.window_height;
+fn sense(agent: Agent, species: Species, sensor_angle_offset: f32) -> f32 {
+    let width = constants.width;
+    let height = constants.height;
 
     let sensor_angle = agent.angle + sensor_angle_offset;
-    let sensor_direction = vec2<f32>(cos(sensor_angle), sin(sensor_angle));
-    let sensor_position = agent.position + sensor_direction * sensor_distance;
-    
+    let sensor_direction = vec2<f32>(cos(sensor_angle), sin(sensor_angle)) * aspect_scale();
+    let sensor_position = agent.position + sensor_direction * species.sensor_distance;
+
+    let radius = i32(max(species.sensor_size, 0.0));
+    let cell_size = vec2<f32>(2.0 / width, 2.0 / height);
+
     var sum = 0.0;
-    var pos = vec2<f32>(sensor_position.x, sensor_position.y);
-    let sample = map_dst.trail[get_cell_index(pos)];
+    var total_weight = 0.0;
+
+    for (var y = -radius; y <= radius; y = y + 1) {
+        for (var x = -radius; x <= radius; x = x + 1) {
+            let weight = kernel_weight(species.sensor_kernel, vec2<i32>(x, y), radius);
 
-    sum = sum + sample.value;
+            if (weight <= 0.0) {
+                continue;
+            }
 
-    return sum;
+            let pos = sensor_position + vec2<f32>(f32(x), f32(y)) * cell_size;
+            let cell_index = get_cell_index(pos);
+
+            if (cell_index >= 0) {
+                sum = sum + dot(map_dst.trail[cell_index].value, species.interaction) * weight;
+            }
+            total_weight = total_weight + weight;
+        }
+    }
+
+    return sum / total_weight;
 }
 
 [[stage(compute), workgroup_size(32)]]
 fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
     var index = global_id.x;
 
-    let width = constants.window_width;
-    let height = constants.window_height;
+    // The last workgroup runs past the end unless `agent_count` is a multiple of 32.
+    if (index >= arrayLength(&agent_src.agents)) {
+        return;
+    }
+
+    let width = constants.width;
+    let height = constants.height;
 
     let TAU = 6.28318530717958647692528676655900577;
     let PI = 3.14159265358979323846264338327950288;
 
     var agent = agent_src.agents[index];
-    let seed = u32(agent.position.y * constants.window_width + agent.position.x);
-    var random = hash(seed + index + param.frame);
+    var random = hash(hash(constants.seed + index) + param.frame);
 
-    let species = species_map.species[0];
+    let species = species_map.species[agent.species];
 
     let sensor_size = species.sensor_size;
     let sensor_angle = species.sensor_angle;
@@ -149,9 +234,9 @@ fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
     let weight = species.weight;
 
     let sensor_angle_rad = sensor_angle * (PI / 180.0);
-    let weight_forward = sense(agent, sensor_size, sensor_distance, 0.0);
-    let weight_left = sense(agent, sensor_size, sensor_distance, sensor_angle_rad);
-    let weight_right = sense(agent, sensor_size, sensor_distance, -sensor_angle_rad);
+    let weight_forward = sense(agent, species, 0.0);
+    let weight_left = sense(agent, species, sensor_angle_rad);
+    let weight_right = sense(agent, species, -sensor_angle_rad);
 
     let mod_turn_speed = turn_speed * TAU;
     let random_steer_strength = scale_to_range_01(random);
@@ -166,25 +251,70 @@ fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
         agent.angle = agent.angle + (random_steer_strength + right_bias) * mod_turn_speed * param.delta_time;
     }
 
-    var direction = vec2<f32>(cos(agent.angle), sin(agent.angle));
+    var direction = vec2<f32>(cos(agent.angle), sin(agent.angle)) * aspect_scale();
     var next_position = vec2<f32>(agent.position.x, agent.position.y) + direction * param.delta_time * move_speed;
     var next_angle = agent.angle;
 
-    if (next_position.x >= 1.0) {
-        next_position.x = -1.0;
+    let outside_x = next_position.x >= 1.0 || next_position.x < -1.0;
+    let outside_y = next_position.y >= 1.0 || next_position.y < -1.0;
+
+    // Reflect
+    if (constants.boundary == 1u) {
+        if (outside_x) {
+            next_position.x = clamp(sign(next_position.x) * 2.0 - next_position.x, -1.0, 1.0);
+            next_angle = PI - next_angle;
+        }
+        if (outside_y) {
+            next_position.y = clamp(sign(next_position.y) * 2.0 - next_position.y, -1.0, 1.0);
+            next_angle = -next_angle;
+        }
     }
-    else if (next_position.x <= -1.0) {
-        next_position.x = 1.0;
+    // Absorb
+    else if (constants.boundary == 2u) {
+        if (outside_x || outside_y) {
+            random = hash(random);
+            next_position.x = scale_to_range_01(random) * 2.0 - 1.0;
+            random = hash(random);
+            next_position.y = scale_to_range_01(random) * 2.0 - 1.0;
+            random = hash(random);
+            next_angle = scale_to_range_01(random) * TAU;
+        }
     }
-    if (next_position.y >= 1.0) {
-        next_position.y = -1.0;
+    // Clamp
+    else if (constants.boundary == 3u) {
+        next_position = clamp(next_position, vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0));
     }
-    else if (next_position.y <= -1.0) {
-        next_position.y = 1.0;
+    // Wrap
+    else {
+        if (next_position.x >= 1.0) {
+            next_position.x = next_position.x - 2.0;
+        }
+        else if (next_position.x < -1.0) {
+            next_position.x = next_position.x + 2.0;
+        }
+        if (next_position.y >= 1.0) {
+            next_position.y = next_position.y - 2.0;
+        }
+        else if (next_position.y < -1.0) {
+            next_position.y = next_position.y + 2.0;
+        }
     }
 
     let map_index = get_cell_index(next_position);
-    map_dst.trail[map_index].value = map_dst.trail[map_index].value + weight;
+
+    // Atomic fixed point adds so no deposit is lost when agents share a cell,
+    // the map pass merges them into the trail next step. An add that wraps is
+    // followed by a max, so crowded cells saturate instead of going dark. Must
+    // match the `saturating_add` in `cpu_simulation.rs`.
+    if (map_index >= 0) {
+        let deposit = u32(floor(max(weight, 0.0) * DEPOSIT_SCALE + 0.5));
+        let cell = u32(map_index) * 4u + agent.species;
+        let previous = atomicAdd(&deposits.deposit[cell], deposit);
+
+        if (previous > 4294967295u - deposit) {
+            atomicMax(&deposits.deposit[cell], 4294967295u);
+        }
+    }
 
     agent.position = next_position;
     agent.angle = next_angle;
@@ -1,7 +1,12 @@
 struct Constants {
-    window_height: f32;
-    window_width: f32;
+    height: f32;
+    width: f32;
     diffusion_rate: f32;
+    boundary: u32;
+    seed: u32;
+    decay_rate: f32;
+    diffusion_kernel: u32;
+    diffusion_radius: u32;
 };
 
 struct Param {
@@ -11,73 +16,201 @@ struct Param {
 
 struct Trail {
     position: vec2<f32>;
-    value: f32;
+    species: u32;
+    padding: u32;
+    value: vec4<f32>;
 };
 
 struct Map {
     trail: array<Trail>;
 };
 
+struct Blur {
+    cell: array<vec4<f32>>;
+};
+
+// Fixed point deposits, `MAX_SPECIES` channels per cell.
+struct Deposits {
+    deposit: array<atomic<u32>>;
+};
+
 
 [[group(0), binding(0)]] var<uniform> constants: Constants;
 [[group(0), binding(1)]] var<uniform> param: Param;
 [[group(0), binding(2)]] var<storage, read> map_src: Map;
 [[group(0), binding(3)]] var<storage, read_write> map_dst: Map;
+[[group(0), binding(4)]] var<storage, read_write> deposits: Deposits;
+[[group(0), binding(5)]] var<storage, read_write> blur: Blur;
+
+// Must match `DEPOSIT_SCALE` in `cpu_simulation.rs`.
+let DEPOSIT_SCALE: f32 = 65536.0;
+
+// Maps a cell coordinate outside the map back inside according to the boundary
+// mode. Returns -1 for cells that are absorbed. Must match `who_cell` in
+// `cpu_simulation.rs`.
+fn boundary_index(p: i32, size: i32) -> i32 {
+    // Reflect
+    if (constants.boundary == 1u) {
+        if (p < 0) {
+            return min(-p - 1, size - 1);
+        }
+        if (p >= size) {
+            return max(2 * size - p - 1, 0);
+        }
+        return p;
+    }
+    // Absorb
+    if (constants.boundary == 2u) {
+        if (p < 0 || p >= size) {
+            return -1;
+        }
+        return p;
+    }
+    // Clamp
+    if (constants.boundary == 3u) {
+        return clamp(p, 0, size - 1);
+    }
+
+    return ((p % size) + size) % size;
+}
 
 fn who_cell(pos: vec2<i32>) -> i32 {
-    let size = i32(constants.window_width);
+    let width = i32(constants.width);
+    let height = i32(constants.height);
+
+    let x = boundary_index(pos.x, width);
+    let y = boundary_index(pos.y, height);
+
+    if (x < 0 || y < 0) {
+        return -1;
+    }
+
+    return (y * width) + x;
+}
+
+fn sample(index: i32) -> vec4<f32> {
+    if (index < 0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+
+    return map_src.trail[index].value;
+}
 
-    var p = pos;
+fn sample_blur(index: i32) -> vec4<f32> {
+    if (index < 0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+
+    return blur.cell[index];
+}
 
-    if (p.x > size) {
-        p.x = 0;
+// One dimensional diffusion weights, 2D kernels are their outer product.
+// Must match `diffusion_weight` in `kernel.rs`.
+fn diffusion_weight(offset: i32) -> f32 {
+    // Gaussian5
+    if (constants.diffusion_kernel == 1u) {
+        let distance = abs(offset);
+        if (distance == 0) {
+            return 6.0;
+        }
+        if (distance == 1) {
+            return 4.0;
+        }
+        return 1.0;
     }
-    else if (p.x < 0) {
-        p.x = size;
+    // SeparableGaussian
+    if (constants.diffusion_kernel == 2u) {
+        let sigma = max(f32(constants.diffusion_radius) / 2.0, 0.5);
+        return exp(-f32(offset * offset) / (2.0 * sigma * sigma));
     }
 
-    if (p.y > size) {
-        p.y = 0;
+    return 1.0;
+}
+
+// First pass of the separable Gaussian, blurs rows of `map_src` into `blur`.
+[[stage(compute), workgroup_size(32)]]
+fn blur_horizontal([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let width = i32(constants.width);
+    let height = i32(constants.height);
+    let index = global_id.x;
+
+    if (i32(index) >= width * height) {
+        return;
     }
-    else if (p.y < 0) {
-        p.y = size;
+
+    let pos = vec2<i32>(i32(index) % width, i32(index) / width);
+    let radius = i32(constants.diffusion_radius);
+
+    var sum = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    var total_weight = 0.0;
+
+    for (var x = -radius; x <= radius; x = x + 1) {
+        let weight = diffusion_weight(x);
+        sum = sum + sample(who_cell(vec2<i32>(pos.x + x, pos.y))) * weight;
+        total_weight = total_weight + weight;
     }
 
-    return (p.y * size) + p.x;
+    blur.cell[index] = sum / total_weight;
 }
 
 [[stage(compute), workgroup_size(32)]]
 fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
-    let size = constants.window_width;
+    let width = i32(constants.width);
+    let height = i32(constants.height);
     let index = global_id.x;
 
-    let diffusion_rate = constants.diffusion_rate;
+    if (i32(index) >= width * height) {
+        return;
+    }
 
-    let distance = 1;
+    let diffusion_rate = clamp(constants.diffusion_rate, 0.0, 1.0);
+    let decay_rate = clamp(constants.decay_rate, 0.0, 1.0);
 
-    let pos = vec2<i32>(i32(index) % i32(size), i32(index) / i32(size));
+    let pos = vec2<i32>(i32(index) % width, i32(index) / width);
+    let radius = i32(constants.diffusion_radius);
 
-    let l_pos = vec2<i32>(pos.x + distance, pos.y);
-    let r_pos = vec2<i32>(pos.x - distance, pos.y);
-    let t_pos = vec2<i32>(pos.x, pos.y - distance);
-    let b_pos = vec2<i32>(pos.x, pos.y + distance);
+    var blurred = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    var total_weight = 0.0;
 
-    let l_index = who_cell(l_pos);
-    let r_index = who_cell(r_pos);
-    let t_index = who_cell(t_pos);
-    let b_index = who_cell(b_pos);
+    // SeparableGaussian, finish with a vertical pass over `blur_horizontal`'s output.
+    if (constants.diffusion_kernel == 2u) {
+        for (var y = -radius; y <= radius; y = y + 1) {
+            let weight = diffusion_weight(y);
+            blurred = blurred + sample_blur(who_cell(vec2<i32>(pos.x, pos.y + y))) * weight;
+            total_weight = total_weight + weight;
+        }
+    }
+    else {
+        for (var y = -radius; y <= radius; y = y + 1) {
+            for (var x = -radius; x <= radius; x = x + 1) {
+                let weight = diffusion_weight(x) * diffusion_weight(y);
+                blurred = blurred + sample(who_cell(vec2<i32>(pos.x + x, pos.y + y))) * weight;
+                total_weight = total_weight + weight;
+            }
+        }
+    }
 
-    var l_value = map_src.trail[l_index].value * diffusion_rate;
-    var r_value = map_src.trail[r_index].value * diffusion_rate;
-    var t_value = map_src.trail[t_index].value * diffusion_rate;
-    var b_value = map_src.trail[b_index].value * diffusion_rate;
+    blurred = blurred / total_weight;
 
-    let m_l = map_src.trail[l_index].value - l_value;
-    let m_r = map_src.trail[r_index].value - r_value;
-    let m_t = map_src.trail[t_index].value - t_value;
-    let m_b = map_src.trail[b_index].value - b_value;
+    let original = map_src.trail[index].value;
+    var value = (original + (blurred - original) * diffusion_rate) * (1.0 - decay_rate);
 
-    let average = (m_l + m_r + m_t + m_b) / 4.0;
+    // Merge and clear the deposits the agent pass accumulated last step.
+    for (var channel = 0u; channel < 4u; channel = channel + 1u) {
+        let deposit = atomicExchange(&deposits.deposit[index * 4u + channel], 0u);
+        value[channel] = value[channel] + f32(deposit) / DEPOSIT_SCALE;
+    }
+
+    var species = map_src.trail[index].species;
+    var strongest = 0.0;
+
+    for (var channel = 0u; channel < 4u; channel = channel + 1u) {
+        if (value[channel] > strongest) {
+            strongest = value[channel];
+            species = channel;
+        }
+    }
 
-    map_dst.trail[index].value = average;
+    map_dst.trail[index].value = value;
+    map_dst.trail[index].species = species;
 }
@@ -1,48 +1,115 @@
+use std::{mem, path::Path};
 use ggez::{Context, GameResult};
-use crate::{construct::*, SimulationConfig, WindowConfig, Param, Storage, ComputeProgram, RenderProgram};
+use crate::{construct::*, util, SimulationConfig, WindowConfig, Param, steps_for, MAX_STEPS_PER_FRAME, Storage, TextureStorage, ComputeProgram, RenderProgram, DiffusionKernel, Species, Snapshot, Constants, Brush, BrushTool, Camera, Palette, AgentStyle, RenderConfig, RenderMode, PostChain, PostSettings, PostConfig};
 
 
 pub struct Simulation {
     pub config: SimulationConfig,
     pub window_config: WindowConfig,
+    pub species: Vec<Species>,
 
+    pub compute_blur_program: ComputeProgram,
     pub compute_map_program: ComputeProgram,
     pub compute_agent_program: ComputeProgram,
+    pub compute_texture_program: ComputeProgram,
     pub render_map_program: RenderProgram,
+    pub render_agents_program: RenderProgram,
+    pub brush_map_program: ComputeProgram,
+    pub brush_agent_program: ComputeProgram,
 
     pub constants_storage: Storage,
+    pub species_storage: Storage,
     pub agent_storage: Storage,
     pub param_storage: Storage,
+    pub param_staging_storage: Storage,
     pub map_storages: Vec<Storage>,
+    pub deposit_storage: Storage,
+    pub blur_storage: Storage,
+    pub brush_storage: Storage,
+    pub camera_storage: Storage,
+    pub palette_storage: Storage,
+    pub agent_style_storage: Storage,
+    pub map_texture: TextureStorage,
+    pub post_chain: PostChain,
+
+    pub camera: Camera,
+    pub palette: Palette,
+    pub agent_style: AgentStyle,
 
     pub frame: usize,
+    pub step_accumulator: f32,
 }
 
 impl Simulation {
     pub fn new(ctx: &mut Context, config: SimulationConfig, window_config: WindowConfig) -> GameResult<Simulation> {
+        let mut species_count = 0;
+        let species = construct_species(&mut species_count)?;
+
+        return Simulation::with_species(ctx, config, species, window_config);
+    }
+
+    pub fn with_species(ctx: &mut Context, config: SimulationConfig, species: Vec<Species>, window_config: WindowConfig) -> GameResult<Simulation> {
         let device = &ctx.gfx.wgpu().device;
 
-        let constants_storage  = construct_constants_storage(device, &window_config, &config)?;
-        let param_storage      = construct_param_storage(device)?;
-        let agent_storage      = construct_agent_storage(device, &config)?;
-        let map_storages       = construct_map_storages(device, &window_config)?;
-        let species_storage    = construct_species_storage(device)?;
+        let constants_storage     = construct_constants_storage(device, &config)?;
+        let param_storage         = construct_param_storage(device)?;
+        let param_staging_storage = construct_param_staging_storage(device, MAX_STEPS_PER_FRAME)?;
+        let agent_storage         = construct_agent_storage(device, &config)?;
+        let map_storages          = construct_map_storages(device, &config)?;
+        let species_storage       = construct_species_storage(device, &species)?;
+        let deposit_storage       = construct_deposit_storage(device, &config)?;
+        let blur_storage          = construct_blur_storage(device, &config)?;
+        let brush_storage         = construct_brush_storage(device)?;
+        let camera                = Camera::new()?;
+        let camera_storage        = construct_camera_storage(device, &camera)?;
+        let palette               = Palette::new(&RenderConfig::default())?;
+        let palette_storage       = construct_palette_storage(device, &palette)?;
+        let agent_style           = AgentStyle::new(&RenderConfig::default(), [window_config.width as f32, window_config.height as f32])?;
+        let agent_style_storage   = construct_agent_style_storage(device, &agent_style)?;
+        let map_texture           = construct_map_texture(device, &config)?;
+        let post_settings         = PostSettings::new(&PostConfig::default(), 0.0)?;
+        let post_chain            = PostChain::new(ctx, [window_config.width as u32, window_config.height as u32], post_settings)?;
 
-        let compute_agent_program = construct_compute_agent_program(ctx, &config, &constants_storage, &agent_storage, &param_storage, &map_storages, &species_storage)?;
-        let compute_map_program   = construct_compute_map_program(ctx, &window_config, &map_storages, &constants_storage, &param_storage)?; 
-        let render_map_program    = construct_render_map_program(ctx, &species_storage)?;
+        let compute_agent_program = construct_compute_agent_program(ctx, &config, &constants_storage, &agent_storage, &param_storage, &map_storages, &species_storage, &deposit_storage)?;
+        let compute_blur_program  = construct_compute_map_program(ctx, &config, &map_storages, &constants_storage, &param_storage, &deposit_storage, &blur_storage, "blur_horizontal")?;
+        let compute_map_program   = construct_compute_map_program(ctx, &config, &map_storages, &constants_storage, &param_storage, &deposit_storage, &blur_storage, "main")?;
+        let compute_texture_program = construct_compute_texture_program(ctx, &config, &constants_storage, &map_storages, &map_texture)?;
+        let render_map_program    = construct_render_map_program(ctx, &species_storage, &camera_storage, &palette_storage, &map_texture)?;
+        let render_agents_program = construct_render_agents_program(ctx, &agent_storage, &species_storage, &camera_storage, &agent_style_storage)?;
+        let brush_map_program     = construct_brush_program(ctx, &config, &constants_storage, &brush_storage, &agent_storage, &map_storages, "brush_map")?;
+        let brush_agent_program   = construct_brush_program(ctx, &config, &constants_storage, &brush_storage, &agent_storage, &map_storages, "brush_agents")?;
 
         let simulation = Simulation { 
             config               , 
             window_config        ,
+            species              ,
+            compute_blur_program ,
             compute_map_program  , 
             compute_agent_program, 
+            compute_texture_program,
             render_map_program   , 
+            render_agents_program,
+            brush_map_program    ,
+            brush_agent_program  ,
             constants_storage    , 
+            species_storage      ,
             agent_storage        , 
             param_storage        , 
+            param_staging_storage,
             map_storages         , 
+            deposit_storage      ,
+            blur_storage         ,
+            brush_storage        ,
+            camera_storage       ,
+            palette_storage      ,
+            agent_style_storage  ,
+            map_texture          ,
+            post_chain           ,
+            camera               ,
+            palette              ,
+            agent_style          ,
             frame: 0             ,
+            step_accumulator: 0.0,
         };
 
         return Ok(simulation);
@@ -50,46 +117,237 @@ impl Simulation {
 
     pub fn reset(&mut self, ctx: &mut Context) -> GameResult {
         let agent_data = construct_agents(&self.config)?;
-        let map_data   = construct_trail_map(&self.window_config)?;
+        let map_data   = construct_trail_map(&self.config)?;
 
         ctx.gfx.wgpu().queue.write_buffer(&self.agent_storage.buffer  , 0, bytemuck::cast_slice(&agent_data));
         ctx.gfx.wgpu().queue.write_buffer(&self.map_storages[0].buffer, 0, bytemuck::cast_slice(&map_data));
         ctx.gfx.wgpu().queue.write_buffer(&self.map_storages[1].buffer, 0, bytemuck::cast_slice(&map_data));
+        ctx.gfx.wgpu().queue.write_buffer(&self.deposit_storage.buffer , 0, &vec![0; self.deposit_storage.size]);
+
+        self.frame = 0;
+        self.step_accumulator = 0.0;
 
         return Ok(());
     }
 
-    pub fn render(&mut self, ctx: &mut Context) -> GameResult {
-        let frame = ctx.gfx.frame().clone();
+    // Switches to a reloaded config. Size changes rebuild every buffer and restart
+    // the run, anything else is written into the existing buffers.
+    pub fn apply_config(&mut self, ctx: &mut Context, config: SimulationConfig, species: Vec<Species>) -> GameResult {
+        if self.config.requires_rebuild(&config) || self.species.len() != species.len() {
+            let (camera, palette, agent_style) = (self.camera, self.palette, self.agent_style);
+            *self = Simulation::with_species(ctx, config, species, self.window_config.clone())?;
+            self.set_camera(ctx, camera)?;
+            self.set_palette(ctx, palette)?;
+
+            return self.set_agent_style(ctx, agent_style);
+        }
+
+        let constants = vec![Constants::new(&config)?];
+
+        ctx.gfx.wgpu().queue.write_buffer(&self.constants_storage.buffer, 0, bytemuck::cast_slice(&constants));
+        ctx.gfx.wgpu().queue.write_buffer(&self.species_storage.buffer  , 0, bytemuck::cast_slice(&species));
+
+        self.config = config;
+        self.species = species;
+
+        return Ok(());
+    }
+
+    // Reads the agents, both trail maps and pending deposits back from the GPU.
+    // Call between frames so every encoded step has been submitted.
+    pub fn save_snapshot(&self, ctx: &mut Context, path: &Path) -> GameResult {
+        let device = &ctx.gfx.wgpu().device;
+        let queue = &ctx.gfx.wgpu().queue;
+
+        let agents  = util::read_buffer(device, queue, &self.agent_storage.buffer  , self.agent_storage.size)?;
+        let map_0   = util::read_buffer(device, queue, &self.map_storages[0].buffer, self.map_storages[0].size)?;
+        let map_1   = util::read_buffer(device, queue, &self.map_storages[1].buffer, self.map_storages[1].size)?;
+        let deposit = util::read_buffer(device, queue, &self.deposit_storage.buffer , self.deposit_storage.size)?;
+
+        let snapshot = Snapshot {
+            config: self.config.clone(),
+            frame: self.frame,
+            species: self.species.clone(),
+            agents: agents,
+            maps: [map_0, map_1],
+            deposit: deposit,
+        };
+
+        return snapshot.save(path);
+    }
+
+    // Rebuilds the simulation around the snapshot's config and species, then
+    // uploads its buffers.
+    pub fn load_snapshot(&mut self, ctx: &mut Context, path: &Path) -> GameResult {
+        let snapshot = Snapshot::load(path)?;
+        let (camera, palette, agent_style) = (self.camera, self.palette, self.agent_style);
+
+        *self = Simulation::with_species(ctx, snapshot.config.clone(), snapshot.species.clone(), self.window_config.clone())?;
+        self.set_camera(ctx, camera)?;
+        self.set_palette(ctx, palette)?;
+        self.set_agent_style(ctx, agent_style)?;
+
+        ctx.gfx.wgpu().queue.write_buffer(&self.agent_storage.buffer  , 0, bytemuck::cast_slice(&snapshot.agents));
+        ctx.gfx.wgpu().queue.write_buffer(&self.map_storages[0].buffer, 0, bytemuck::cast_slice(&snapshot.maps[0]));
+        ctx.gfx.wgpu().queue.write_buffer(&self.map_storages[1].buffer, 0, bytemuck::cast_slice(&snapshot.maps[1]));
+        ctx.gfx.wgpu().queue.write_buffer(&self.deposit_storage.buffer , 0, bytemuck::cast_slice(&snapshot.deposit));
+
+        self.frame = snapshot.frame;
+
+        return Ok(());
+    }
+
+    pub fn set_camera(&mut self, ctx: &mut Context, camera: Camera) -> GameResult {
+        ctx.gfx.wgpu().queue.write_buffer(&self.camera_storage.buffer, 0, bytemuck::cast_slice(&[camera]));
+
+        self.camera = camera;
+
+        return Ok(());
+    }
+
+    pub fn set_palette(&mut self, ctx: &mut Context, palette: Palette) -> GameResult {
+        ctx.gfx.wgpu().queue.write_buffer(&self.palette_storage.buffer, 0, bytemuck::cast_slice(&[palette]));
+
+        self.palette = palette;
+
+        return Ok(());
+    }
+
+    pub fn set_agent_style(&mut self, ctx: &mut Context, agent_style: AgentStyle) -> GameResult {
+        ctx.gfx.wgpu().queue.write_buffer(&self.agent_style_storage.buffer, 0, bytemuck::cast_slice(&[agent_style]));
+
+        self.agent_style = agent_style;
+
+        return Ok(());
+    }
+
+    pub fn set_post_settings(&mut self, ctx: &mut Context, post_settings: PostSettings) -> GameResult {
+        return self.post_chain.set_post_settings(ctx, post_settings);
+    }
+
+    // Rebuilds the post chain's textures for a new window size.
+    pub fn resize(&mut self, ctx: &mut Context, size: [u32; 2]) -> GameResult {
+        let capturing = self.post_chain.capture.is_some();
 
-        let delta_time = ctx.time.delta().as_secs_f32();
-        let param = [Param { delta_time, frame: self.frame as u32 }];
+        self.post_chain = PostChain::new(ctx, size, self.post_chain.post_settings)?;
+
+        return self.post_chain.set_capturing(ctx, capturing);
+    }
+
+    // Keeps the resources `render` needs to capture frames for recording.
+    pub fn set_capturing(&mut self, ctx: &mut Context, capturing: bool) -> GameResult {
+        return self.post_chain.set_capturing(ctx, capturing);
+    }
+
+    // The last frame rendered with `capture`, as RGBA8 rows from the top and
+    // the frame's size. Call between frames.
+    pub fn read_capture(&mut self, ctx: &mut Context) -> GameResult<Option<(Vec<u8>, [u32; 2])>> {
+        let size = self.post_chain.size;
+
+        return Ok(self.post_chain.read_capture(ctx)?.map(|frame| (frame, size)));
+    }
+
+    // Encodes one brush stroke onto the current trail map or the agents, ahead of
+    // any steps encoded this frame. Only one stroke fits in a frame since the
+    // uniform is written through the queue.
+    pub fn apply_brush(&mut self, ctx: &mut Context, brush: Brush) -> GameResult {
+        ctx.gfx.wgpu().queue.write_buffer(&self.brush_storage.buffer, 0, bytemuck::cast_slice(&[brush]));
 
-        ctx.gfx.wgpu().queue.write_buffer(&self.param_storage.buffer, 0, bytemuck::cast_slice(&param));
-        
         let command_encoder = ctx.gfx.commands().unwrap();
 
-        self.compute_map_program.process(command_encoder, self.frame)?;
-        self.compute_agent_program.process(command_encoder, self.frame)?;
-
-        command_encoder.push_debug_group("Render Map");
-        {
-            let window_area = (self.window_config.width * self.window_config.height) as u32;
-            let color_attachments = &[wgpu::RenderPassColorAttachment {
-                view: frame.wgpu().1,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: true,
-                },
-            }];
-
-            self.render_map_program.process(command_encoder, color_attachments, vec![&self.map_storages[(self.frame + 1) % 2]], 0..1, 0..window_area, self.frame)?;
+        if BrushTool::from_index(brush.tool).affects_agents() {
+            self.brush_agent_program.process(command_encoder, self.frame)?;
+        } else {
+            self.brush_map_program.process(command_encoder, self.frame)?;
         }
-        command_encoder.pop_debug_group();
-        
-        self.frame += 1;
 
         return Ok(());
     }
+
+    // Runs the steps owed for one rendered frame, see `steps_for`.
+    pub fn advance(&mut self, ctx: &mut Context) -> GameResult {
+        let (steps, step_accumulator) = steps_for(self.step_accumulator, self.config.substeps, self.config.time_scale);
+        self.step_accumulator = step_accumulator;
+
+        return self.step(ctx, steps);
+    }
+
+    // Encodes `steps` fixed size simulation steps without drawing anything.
+    pub fn step(&mut self, ctx: &mut Context, steps: usize) -> GameResult {
+        if steps == 0 { return Ok(()); }
+
+        let param_size = mem::size_of::<Param>();
+        let params: Vec<Param> = (0..steps)
+            .map(|i| Param { delta_time: self.config.time_step, frame: (self.frame + i) as u32 })
+            .collect();
+
+        // Every queue write lands before the encoder runs, so each step copies its
+        // own parameters into the uniform between passes instead.
+        ctx.gfx.wgpu().queue.write_buffer(&self.param_staging_storage.buffer, 0, bytemuck::cast_slice(&params));
+
+        let command_encoder = ctx.gfx.commands().unwrap();
+
+        for i in 0..steps {
+            command_encoder.copy_buffer_to_buffer(&self.param_staging_storage.buffer, (i * param_size) as u64, &self.param_storage.buffer, 0, param_size as u64);
+
+            if let DiffusionKernel::SeparableGaussian(_) = self.config.diffusion_kernel {
+                self.compute_blur_program.process(command_encoder, self.frame)?;
+            }
+            self.compute_map_program.process(command_encoder, self.frame)?;
+            self.compute_agent_program.process(command_encoder, self.frame)?;
+
+            self.frame += 1;
+        }
+
+        return Ok(());
+    }
+
+    // Draws the trail and agents into the post chain's HDR texture, then runs the
+    // chain onto the window. With `capture` the frame is also kept for
+    // `read_capture`, see `set_capturing`.
+    pub fn render(&mut self, ctx: &mut Context, render_mode: RenderMode, capture: bool) -> GameResult {
+        let frame = ctx.gfx.frame().clone();
+        let command_encoder = ctx.gfx.commands().unwrap();
+
+        if render_mode.shows_trail() {
+            command_encoder.push_debug_group("Render Map");
+            {
+                // The last step wrote into the buffer at the current frame parity.
+                self.compute_texture_program.process(command_encoder, self.frame)?;
+
+                let color_attachments = &[wgpu::RenderPassColorAttachment {
+                    view: &self.post_chain.hdr_texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }];
+
+                self.render_map_program.process(command_encoder, color_attachments, vec![], 0..3, 0..1, self.frame)?;
+            }
+            command_encoder.pop_debug_group();
+        }
+
+        if render_mode.shows_agents() {
+            command_encoder.push_debug_group("Render Agents");
+            {
+                let load = if render_mode.shows_trail() { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(wgpu::Color::BLACK) };
+                let color_attachments = &[wgpu::RenderPassColorAttachment {
+                    view: &self.post_chain.hdr_texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: true },
+                }];
+
+                // Six vertices per agent for each tile the camera draws.
+                let vertex_count = 6 * self.camera.tile_count();
+                let instance_count = self.agent_style.drawn_agents(self.config.agent_count);
+
+                self.render_agents_program.process(command_encoder, color_attachments, vec![], 0..vertex_count, 0..instance_count, self.frame)?;
+            }
+            command_encoder.pop_debug_group();
+        }
+
+        return self.post_chain.process(command_encoder, frame.wgpu().1, capture);
+    }
 }
@@ -0,0 +1,157 @@
+use std::{fs::{self, File}, io::{Read, Write, BufReader, BufWriter}, mem, path::Path};
+use serde::{Serialize, Deserialize};
+use ggez::{GameResult, GameError};
+use crate::{SimulationConfig, Species, Agent, Trail, MAX_SPECIES};
+
+
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"PHYSARUM";
+// Bump whenever the header or any of the buffer layouts change.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// Stored as RON after the magic and version, followed by the raw species, agent,
+// trail and deposit buffers in that order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub config: SimulationConfig,
+    pub frame: u64,
+    pub species_count: u32,
+    pub agent_count: u32,
+    pub cell_count: u32,
+}
+
+// Full simulation state. `maps` is in buffer order, the current trail map is
+// `maps[frame % 2]`. `deposit` holds the deposits waiting for the next map pass.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub config: SimulationConfig,
+    pub frame: usize,
+    pub species: Vec<Species>,
+    pub agents: Vec<Agent>,
+    pub maps: [Vec<Trail>; 2],
+    pub deposit: Vec<u32>,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> GameResult {
+        if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+
+        return Ok(());
+    }
+
+    pub fn load(path: &Path) -> GameResult<Snapshot> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        return Snapshot::read(&mut reader);
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> GameResult {
+        let header = SnapshotHeader {
+            config: self.config.clone(),
+            frame: self.frame as u64,
+            species_count: self.species.len() as u32,
+            agent_count: self.agents.len() as u32,
+            cell_count: self.maps[0].len() as u32,
+        };
+        let header = ron::to_string(&header).map_err(|e| GameError::CustomError(format!("Failed to encode snapshot header: {}", e)))?;
+
+        writer.write_all(&SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&(header.len() as u32).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+
+        writer.write_all(bytemuck::cast_slice(&self.species))?;
+        writer.write_all(bytemuck::cast_slice(&self.agents))?;
+        writer.write_all(bytemuck::cast_slice(&self.maps[0]))?;
+        writer.write_all(bytemuck::cast_slice(&self.maps[1]))?;
+        writer.write_all(bytemuck::cast_slice(&self.deposit))?;
+
+        return Ok(());
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> GameResult<Snapshot> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+
+        if magic != SNAPSHOT_MAGIC {
+            return Err(GameError::ResourceLoadError(String::from("Not a physarum snapshot")));
+        }
+
+        let version = read_u32(reader)?;
+
+        if version != SNAPSHOT_VERSION {
+            return Err(GameError::ResourceLoadError(format!("Snapshot version {} is not supported, expected {}", version, SNAPSHOT_VERSION)));
+        }
+
+        let header_len = read_u32(reader)? as usize;
+        let header = String::from_utf8(read_bytes(reader, header_len)?).map_err(|e| GameError::ResourceLoadError(format!("Snapshot header is not valid UTF-8: {}", e)))?;
+        let header: SnapshotHeader = ron::from_str(&header).map_err(|e| GameError::ResourceLoadError(format!("Failed to decode snapshot header: {}", e)))?;
+
+        // Everything below is uploaded into fixed size GPU buffers, so a corrupt
+        // or edited snapshot has to be turned away here.
+        if let Some(error) = header.config.validate().first() {
+            return Err(GameError::ResourceLoadError(format!("Snapshot config is invalid: {}", error)));
+        }
+
+        let cell_count = header.config.width as usize * header.config.height as usize;
+
+        if header.cell_count as usize != cell_count || header.agent_count != header.config.agent_count {
+            return Err(GameError::ResourceLoadError(String::from("Snapshot buffer sizes do not match its config")));
+        }
+
+        if header.species_count == 0 || header.species_count as usize > MAX_SPECIES {
+            return Err(GameError::ResourceLoadError(format!("Snapshot has {} species, expected 1 to {}", header.species_count, MAX_SPECIES)));
+        }
+
+        let species = read_pod(reader, header.species_count as usize)?;
+        let agents: Vec<Agent> = read_pod(reader, header.agent_count as usize)?;
+        let map_0   = read_pod(reader, cell_count)?;
+        let map_1   = read_pod(reader, cell_count)?;
+        let deposit = read_pod(reader, cell_count * MAX_SPECIES)?;
+
+        if let Some(agent) = agents.iter().find(|agent| agent.species >= header.species_count) {
+            return Err(GameError::ResourceLoadError(format!("Snapshot agent has species {} but there are only {} species", agent.species, header.species_count)));
+        }
+
+        let snapshot = Snapshot {
+            config: header.config,
+            frame: header.frame as usize,
+            species,
+            agents,
+            maps: [map_0, map_1],
+            deposit,
+        };
+
+        return Ok(snapshot);
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> GameResult<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    return Ok(u32::from_le_bytes(bytes));
+}
+
+// Grows with the data actually read, so a bogus length in a truncated file
+// fails instead of allocating it up front.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> GameResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len {
+        return Err(GameError::ResourceLoadError(String::from("Snapshot ends before its buffers")));
+    }
+
+    return Ok(bytes);
+}
+
+fn read_pod<T: bytemuck::Pod, R: Read>(reader: &mut R, count: usize) -> GameResult<Vec<T>> {
+    let len = count.checked_mul(mem::size_of::<T>()).ok_or_else(|| GameError::ResourceLoadError(String::from("Snapshot buffer is too large")))?;
+    let bytes = read_bytes(reader, len)?;
+
+    return Ok(bytes.chunks_exact(mem::size_of::<T>()).map(bytemuck::pod_read_unaligned).collect());
+}
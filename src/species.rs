@@ -1,7 +1,10 @@
 use ggez::{GameResult};
-use crate::{SpeciesConfig};
+use crate::{SpeciesConfig, SensorKernel};
 
 
+// Trail cells carry one channel per species in a `vec4<f32>`.
+pub const MAX_SPECIES: usize = 4;
+
 #[repr(C)]
 #[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug)]
 pub struct Species {
@@ -17,10 +20,19 @@ pub struct Species {
     pub color_r: f32,
     pub color_g: f32,
     pub color_b: f32,
+    pub interaction: [f32; MAX_SPECIES],
+    pub sensor_kernel: u32,
+    pub _padding: [u32; 3],
 }
 
 impl Species {
-    pub fn new(config: SpeciesConfig) -> GameResult<Species> {
+    pub fn new(config: SpeciesConfig, index: usize) -> GameResult<Species> {
+        let mut interaction = [0.0; MAX_SPECIES];
+        let [color_r, color_g, color_b] = config.rgb()?;
+
+        if config.interactions.is_empty() { interaction[index] = 1.0; }
+        config.interactions.iter().take(MAX_SPECIES).enumerate().for_each(|(i, value)| interaction[i] = *value);
+
         let species = Species { 
             sensor_size: config.sensor_size,
             sensor_angle: config.sensor_angle,
@@ -31,11 +43,38 @@ impl Species {
             left_bias: config.left_bias,
             right_bias: config.right_bias,
             weight: config.weight,
-            color_r: config.color_r,
-            color_g: config.color_g,
-            color_b: config.color_b,
+            color_r,
+            color_g,
+            color_b,
+            interaction,
+            sensor_kernel: config.sensor_kernel as u32,
+            _padding: [0; 3],
          };
 
         return Ok(species);
     }
+
+    // The config that builds this species again, for species that only exist on
+    // the GPU such as ones restored from a snapshot.
+    pub fn to_config(&self, species_count: usize) -> GameResult<SpeciesConfig> {
+        let config = SpeciesConfig {
+            sensor_size: self.sensor_size,
+            sensor_kernel: SensorKernel::from_index(self.sensor_kernel),
+            sensor_angle: self.sensor_angle,
+            sensor_distance: self.sensor_distance,
+            turn_speed: self.turn_speed,
+            move_speed: self.move_speed,
+            forward_bias: self.forward_bias,
+            left_bias: self.left_bias,
+            right_bias: self.right_bias,
+            weight: self.weight,
+            color_r: self.color_r,
+            color_g: self.color_g,
+            color_b: self.color_b,
+            color: None,
+            interactions: self.interaction[..species_count.min(MAX_SPECIES)].to_vec(),
+        };
+
+        return Ok(config);
+    }
 }
@@ -2,3 +2,17 @@ pub struct Storage {
     pub size: usize,
     pub buffer: wgpu::Buffer,
 }
+
+// Filterable, and writable from a compute shader without extra device features.
+pub const MAP_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Keeps colours above 1 for the post chain to expose and tone map.
+pub const HDR_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub struct TextureStorage {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+// Frames read back for recording, encoded like an sRGB window would.
+pub const CAPTURE_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
@@ -1,23 +1,6 @@
 use super::*;
 
 
-fn calculate_pixel_list(radius: i32) -> Vec<(i32, i32)> {
-    let mut pixel_list = vec![(0,0)];
-
-    if radius > 0 {
-        for y in -radius..radius + 1 {
-            for x in -radius..radius + 1{
-                let t = (x, y);
-                if t == (0,0) { continue; }
-
-                pixel_list.push(t);
-            }
-        }
-    }
-
-    return pixel_list;
-}
-
 #[test]
 fn pixel_radius_0() {
     let radius = 0;
@@ -51,3 +34,1176 @@ fn pixel_radius_2() {
 
     assert_eq!(pixel_list, target_pixel_list);
 }
+
+fn test_config(width: i32, height: i32, agent_count: u32) -> SimulationConfig {
+    let simulation_config = SimulationConfig {
+        agent_count,
+        seed: 0,
+        width,
+        height,
+        diffusion_rate: 1.0,
+        decay_rate: 0.1,
+        diffusion_kernel: DiffusionKernel::Box3,
+        boundary: Boundary::Wrap,
+        time_step: 1.0 / 60.0,
+        substeps: 1,
+        time_scale: 1.0,
+        pattern: Pattern::Random,
+        heading: None,
+        spawn_radius: 0.5,
+    };
+
+    return simulation_config;
+}
+
+fn test_species_config() -> SpeciesConfig {
+    let config = SpeciesConfig {
+        sensor_size: 0.0,
+        sensor_kernel: SensorKernel::Square,
+        sensor_angle: 45.0,
+        sensor_distance: 0.1,
+        turn_speed: 1.0,
+        move_speed: 0.5,
+        forward_bias: 0.0,
+        left_bias: 0.0,
+        right_bias: 0.0,
+        weight: 0.5,
+        color_r: 1.0,
+        color_g: 1.0,
+        color_b: 1.0,
+        color: None,
+        interactions: vec![],
+    };
+
+    return config;
+}
+
+fn test_species() -> Species {
+    return Species::new(test_species_config(), 0).unwrap();
+}
+
+#[test]
+fn cpu_cell_index_wraps() {
+    let simulation_config = test_config(8, 8, 0);
+
+    assert_eq!(get_cell_index([-0.99, -0.99], &simulation_config), Some(0));
+    assert_eq!(get_cell_index([0.99, 0.99], &simulation_config), Some(63));
+    assert_eq!(get_cell_index([-1.0, 0.0], &simulation_config), get_cell_index([1.0, 0.0], &simulation_config));
+    assert_eq!(who_cell((-1, 0), &simulation_config), Some(7));
+    assert_eq!(who_cell((8, 8), &simulation_config), Some(0));
+}
+
+#[test]
+fn cpu_map_diffuses_uniform_field() {
+    let simulation_config = test_config(8, 8, 0);
+    let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+    trail_map.iter_mut().for_each(|trail| trail.value = [1.0; MAX_SPECIES]);
+
+    let mut deposit = vec![0; trail_map.len() * MAX_SPECIES];
+    let trail_map = update_map(&trail_map, &mut deposit, &simulation_config).unwrap();
+
+    assert!(trail_map.iter().all(|trail| trail.value.iter().all(|value| (value - 0.9).abs() < 1e-6)));
+}
+
+#[test]
+fn cpu_agents_deposit_weight() {
+    let simulation_config = test_config(16, 16, 4);
+    let species = test_species();
+    let agents = vec![Agent::default().unwrap(); 4];
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![species], agents, trail_map).unwrap();
+    simulation.step(0.01).unwrap();
+
+    assert!((deposit_mass(&simulation.deposit) - 4.0 * species.weight).abs() < 1e-6);
+    assert_eq!(simulation.frame, 1);
+
+    // Deposits are merged into the trail by the next map pass.
+    simulation.step(0.01).unwrap();
+
+    let total: f32 = simulation.trail_map.iter().map(|trail| trail.total()).sum();
+
+    assert!((total - 4.0 * species.weight).abs() < 1e-6);
+}
+
+#[test]
+fn cpu_agents_use_own_species() {
+    let simulation_config = test_config(16, 16, 1);
+    let mut heavy = test_species();
+    heavy.weight = 2.0;
+    let agents = vec![Agent::default().unwrap().with_species(1).unwrap()];
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(), heavy], agents, trail_map).unwrap();
+    simulation.step(0.01).unwrap();
+    simulation.step(0.01).unwrap();
+
+    let deposit = simulation.trail_map.iter().find(|trail| trail.total() > 0.0).unwrap();
+
+    assert_eq!(deposit.value, [0.0, 2.0, 0.0, 0.0]);
+    assert_eq!(deposit.species, 1);
+}
+
+#[test]
+fn cpu_sense_uses_interaction_matrix() {
+    let simulation_config = test_config(16, 16, 1);
+    let mut species = test_species();
+    species.interaction = [1.0, -2.0, 0.0, 0.0];
+    let agent = Agent::default().unwrap();
+    let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+
+    let index = get_cell_index([species.sensor_distance, 0.0], &simulation_config).unwrap();
+    trail_map[index].value = [1.0, 1.0, 5.0, 0.0];
+
+    let kernel = calculate_kernel(SensorKernel::Square, species.sensor_size);
+
+    assert_eq!(sense(&agent, &species, &kernel, &trail_map, &simulation_config, 0.0), -1.0);
+}
+
+#[test]
+fn kernel_weights_are_normalised() {
+    for kernel in [SensorKernel::Square, SensorKernel::Disc, SensorKernel::Gaussian] {
+        for sensor_size in [0.0, 1.0, 2.5] {
+            let total: f32 = calculate_kernel(kernel, sensor_size).iter().map(|(_, weight)| weight).sum();
+
+            assert!((total - 1.0).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn kernel_shapes() {
+    assert_eq!(calculate_kernel(SensorKernel::Square, 1.0).len(), 9);
+    assert_eq!(calculate_kernel(SensorKernel::Disc, 1.0).len(), 5);
+    assert_eq!(calculate_kernel(SensorKernel::Disc, 2.0).len(), 13);
+
+    let gaussian = calculate_kernel(SensorKernel::Gaussian, 2.0);
+    let centre = gaussian.iter().find(|(offset, _)| *offset == (0, 0)).unwrap().1;
+    let corner = gaussian.iter().find(|(offset, _)| *offset == (2, 2)).unwrap().1;
+
+    assert!(centre > corner);
+}
+
+#[test]
+fn cpu_sense_averages_over_kernel() {
+    let simulation_config = test_config(16, 16, 1);
+    let mut species = test_species();
+    species.sensor_size = 1.0;
+    let agent = Agent::default().unwrap();
+    let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+
+    let index = get_cell_index([species.sensor_distance, 0.0], &simulation_config).unwrap();
+    trail_map[index].value = [9.0, 0.0, 0.0, 0.0];
+
+    let kernel = calculate_kernel(SensorKernel::Square, species.sensor_size);
+
+    assert!((sense(&agent, &species, &kernel, &trail_map, &simulation_config, 0.0) - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn cpu_rectangular_map_indexing() {
+    let simulation_config = test_config(8, 4, 0);
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+
+    assert_eq!(trail_map.len(), 32);
+    assert_eq!(get_cell_index([0.99, 0.99], &simulation_config), Some(31));
+    assert_eq!(get_cell_index([-0.99, 0.99], &simulation_config), Some(24));
+    assert_eq!(who_cell((8, 3), &simulation_config), Some(24));
+    assert_eq!(who_cell((0, 4), &simulation_config), Some(0));
+
+    for (index, trail) in trail_map.iter().enumerate() {
+        let centre = [trail.position[0] + 1.0 / 8.0, trail.position[1] + 1.0 / 4.0];
+
+        assert_eq!(get_cell_index(centre, &simulation_config), Some(index));
+    }
+}
+
+#[test]
+fn cpu_boundary_cells() {
+    let mut simulation_config = test_config(8, 8, 0);
+
+    simulation_config.boundary = Boundary::Reflect;
+    assert_eq!(who_cell((-1, 0), &simulation_config), Some(0));
+    assert_eq!(who_cell((9, 0), &simulation_config), Some(6));
+
+    simulation_config.boundary = Boundary::Clamp;
+    assert_eq!(who_cell((-3, 9), &simulation_config), Some(56));
+
+    simulation_config.boundary = Boundary::Absorb;
+    assert_eq!(who_cell((-1, 0), &simulation_config), None);
+    assert_eq!(who_cell((7, 7), &simulation_config), Some(63));
+}
+
+#[test]
+fn cpu_boundary_agents() {
+    let mut simulation_config = test_config(8, 8, 0);
+    let mut agent = Agent { position_x: 1.1, position_y: 0.0, angle: 0.0, species: 0 };
+
+    simulation_config.boundary = Boundary::Wrap;
+    apply_boundary(&mut agent, 0, &simulation_config);
+    assert!((agent.position_x + 0.9).abs() < 1e-6);
+
+    agent.position_x = 1.1;
+    simulation_config.boundary = Boundary::Reflect;
+    apply_boundary(&mut agent, 0, &simulation_config);
+    assert!((agent.position_x - 0.9).abs() < 1e-6);
+    assert!((agent.angle - std::f32::consts::PI).abs() < 1e-6);
+
+    agent.position_x = 1.1;
+    simulation_config.boundary = Boundary::Clamp;
+    apply_boundary(&mut agent, 0, &simulation_config);
+    assert_eq!(agent.position_x, 1.0);
+
+    agent.position_x = 1.1;
+    simulation_config.boundary = Boundary::Absorb;
+    apply_boundary(&mut agent, 0, &simulation_config);
+    assert!(agent.position_x >= -1.0 && agent.position_x < 1.0);
+}
+
+#[test]
+fn cpu_diffusion_conserves_mass() {
+    for diffusion_kernel in [DiffusionKernel::Box3, DiffusionKernel::Gaussian5, DiffusionKernel::SeparableGaussian(3)] {
+        let mut simulation_config = test_config(16, 16, 0);
+        simulation_config.decay_rate = 0.0;
+        simulation_config.diffusion_kernel = diffusion_kernel;
+
+        let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+        trail_map[0].value = [1.0, 0.0, 0.0, 0.0];
+        trail_map[100].value = [0.0, 2.0, 0.0, 0.0];
+
+        let mut deposit = vec![0; trail_map.len() * MAX_SPECIES];
+        let trail_map = update_map(&trail_map, &mut deposit, &simulation_config).unwrap();
+        let total: f32 = trail_map.iter().map(|trail| trail.total()).sum();
+
+        assert!((total - 3.0).abs() < 1e-5);
+        // A point spreads symmetrically, across the wrapped edge too.
+        assert!((trail_map[1].value[0] - trail_map[15].value[0]).abs() < 1e-6);
+        assert!((trail_map[16].value[0] - trail_map[240].value[0]).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn cpu_decay_is_independent_of_diffusion() {
+    let mut simulation_config = test_config(8, 8, 0);
+    simulation_config.diffusion_rate = 0.0;
+    simulation_config.decay_rate = 0.5;
+
+    let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+    trail_map[9].value = [1.0, 0.0, 0.0, 0.0];
+
+    let mut deposit = vec![0; trail_map.len() * MAX_SPECIES];
+    let trail_map = update_map(&trail_map, &mut deposit, &simulation_config).unwrap();
+
+    assert_eq!(trail_map[9].value[0], 0.5);
+    assert_eq!(trail_map[10].value[0], 0.0);
+}
+
+#[test]
+fn cpu_absorbing_boundary_drains_trail() {
+    let mut simulation_config = test_config(8, 8, 0);
+    simulation_config.decay_rate = 0.0;
+    let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+    trail_map.iter_mut().for_each(|trail| trail.value = [1.0; MAX_SPECIES]);
+
+    let mut deposit = vec![0; trail_map.len() * MAX_SPECIES];
+    let wrapped: f32 = update_map(&trail_map, &mut deposit, &simulation_config).unwrap().iter().map(|trail| trail.total()).sum();
+
+    simulation_config.boundary = Boundary::Absorb;
+    let absorbed: f32 = update_map(&trail_map, &mut deposit, &simulation_config).unwrap().iter().map(|trail| trail.total()).sum();
+
+    assert!((wrapped - 256.0).abs() < 1e-3);
+    assert!(absorbed < wrapped);
+}
+
+#[test]
+fn spawn_patterns() {
+    let mut simulation_config = test_config(16, 16, 256);
+
+    simulation_config.pattern = Pattern::Center;
+    assert!(construct_agents(&simulation_config).unwrap().iter().all(|agent| agent.position_x == 0.0 && agent.position_y == 0.0));
+
+    simulation_config.pattern = Pattern::Ring;
+    simulation_config.spawn_radius = 0.5;
+    for agent in construct_agents(&simulation_config).unwrap() {
+        let distance = (agent.position_x * agent.position_x + agent.position_y * agent.position_y).sqrt();
+        assert!((distance - 0.5).abs() < 1e-4);
+    }
+
+    simulation_config.pattern = Pattern::InwardCircle;
+    for agent in construct_agents(&simulation_config).unwrap() {
+        let distance = (agent.position_x * agent.position_x + agent.position_y * agent.position_y).sqrt();
+        let facing = agent.position_x * agent.angle.cos() + agent.position_y * agent.angle.sin();
+        assert!(distance <= 0.5 + 1e-4);
+        assert!(facing <= 1e-4);
+    }
+
+    simulation_config.heading = Some(Heading::Outward);
+    for agent in construct_agents(&simulation_config).unwrap() {
+        let facing = agent.position_x * agent.angle.cos() + agent.position_y * agent.angle.sin();
+        assert!(facing >= -1e-4);
+    }
+}
+
+#[test]
+fn spawn_mask_uses_bright_pixels() {
+    let path = std::env::temp_dir().join("physarum_test_mask.png");
+    let file = std::fs::File::create(&path).unwrap();
+
+    let mut encoder = png::Encoder::new(file, 4, 2);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&[255, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+
+    let mask = Mask::load(path.to_str().unwrap()).unwrap();
+    let mut rng = rand::thread_rng();
+
+    assert_eq!(mask.cells, vec![(0, 0)]);
+
+    for _ in 0..64 {
+        let position = mask.random_position(&mut rng);
+        assert!(position[0] >= -1.0 && position[0] <= -0.5);
+        assert!(position[1] >= 0.0 && position[1] <= 1.0);
+    }
+}
+
+#[test]
+fn seeded_runs_are_reproducible() {
+    // Not a multiple of the 32 wide agent workgroups, the shader skips the rest.
+    let mut simulation_config = test_config(32, 32, 500);
+    simulation_config.seed = 7;
+
+    let run = |simulation_config: &SimulationConfig| {
+        let agents = construct_agents(simulation_config).unwrap();
+        let trail_map = construct_trail_map(simulation_config).unwrap();
+        let mut simulation = CpuSimulation::from_state(simulation_config.clone(), vec![test_species(); MAX_SPECIES], agents, trail_map).unwrap();
+
+        for _ in 0..10 { simulation.step(1.0 / 60.0).unwrap(); }
+
+        return simulation;
+    };
+
+    let a = run(&simulation_config);
+    let b = run(&simulation_config);
+
+    assert_eq!(bytemuck::cast_slice::<Agent, u8>(&a.agents), bytemuck::cast_slice::<Agent, u8>(&b.agents));
+    assert_eq!(bytemuck::cast_slice::<Trail, u8>(&a.trail_map), bytemuck::cast_slice::<Trail, u8>(&b.trail_map));
+
+    simulation_config.seed = 8;
+    let c = run(&simulation_config);
+
+    assert_ne!(bytemuck::cast_slice::<Agent, u8>(&a.agents), bytemuck::cast_slice::<Agent, u8>(&c.agents));
+}
+
+#[test]
+fn cpu_deposit_mass_is_exact() {
+    let simulation_config = test_config(16, 16, 1000);
+    let mut species = test_species();
+    species.weight = 0.02;
+    let agents = vec![Agent::default().unwrap(); 1000];
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![species], agents, trail_map).unwrap();
+
+    for _ in 0..3 {
+        simulation.step(0.01).unwrap();
+
+        let expected = 1000.0 * quantize_deposit(species.weight) as f32 / DEPOSIT_SCALE;
+
+        assert_eq!(deposit_mass(&simulation.deposit), expected);
+        assert!((expected - 1000.0 * species.weight).abs() < 1000.0 / DEPOSIT_SCALE);
+    }
+}
+
+#[test]
+fn headless_args() {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+
+    assert_eq!(HeadlessOptions::from_args(&args(&["physarum"])).unwrap(), None);
+
+    let options = HeadlessOptions::from_args(&args(&["physarum", "--headless", "--steps", "20", "--output", "frames"])).unwrap().unwrap();
+    assert_eq!(options, HeadlessOptions { steps: 20, interval: 20, output: "frames".into(), record: None });
+
+    assert!(HeadlessOptions::from_args(&args(&["physarum", "--headless", "--steps", "ten"])).is_err());
+    assert!(HeadlessOptions::from_args(&args(&["physarum", "--headless", "--interval"])).is_err());
+}
+
+#[test]
+fn headless_writes_frames() {
+    let simulation_config = test_config(8, 4, 16);
+    let agents = construct_agents(&simulation_config).unwrap();
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(); MAX_SPECIES], agents, trail_map).unwrap();
+
+    let output = std::env::temp_dir().join(format!("physarum_headless_{}", std::process::id()));
+    let options = HeadlessOptions { steps: 5, interval: 2, output: output.clone(), record: None };
+    run_batch(&mut simulation, &Palette::new(&RenderConfig::default()).unwrap(), &PostSettings::new(&PostConfig::default(), 0.0).unwrap(), &options).unwrap();
+
+    for step in [2, 4, 5] {
+        let decoder = png::Decoder::new(std::fs::File::open(output.join(format!("frame_{:06}.png", step))).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (8, 4));
+
+        let raw = std::fs::read(output.join(format!("frame_{:06}.f32", step))).unwrap();
+        assert_eq!(raw.len(), 8 * 4 * MAX_SPECIES * 4);
+    }
+    assert!(!output.join("frame_000003.png").exists());
+
+    std::fs::remove_dir_all(&output).unwrap();
+}
+
+#[test]
+fn headless_bundled_render_config_keeps_default_look() {
+    let simulation_config = test_config(8, 4, 64);
+    let agents = construct_agents(&simulation_config).unwrap();
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(); MAX_SPECIES], agents, trail_map).unwrap();
+    for _ in 0..5 { simulation.step(1.0 / 60.0).unwrap(); }
+
+    let bundled = load_render_config(&ConfigRoot::Bundled).unwrap();
+    let frame = |render_config: &RenderConfig| {
+        let palette = Palette::new(render_config).unwrap();
+        let post_settings = PostSettings::new(&render_config.post, 0.0).unwrap();
+
+        return trail_map_to_rgba(&simulation.trail_map, &simulation.species, &palette, &post_settings, 8, 4);
+    };
+
+    assert!(frame(&bundled).chunks(4).any(|pixel| pixel[..3] != [0, 0, 0]));
+    assert_eq!(frame(&bundled), frame(&RenderConfig::default()));
+}
+
+#[test]
+fn headless_image_matches_render_colours() {
+    let simulation_config = test_config(2, 2, 0);
+    let mut trail_map = construct_trail_map(&simulation_config).unwrap();
+    trail_map[0].value = [1.0, 0.0, 0.0, 0.0];
+    let mut species = test_species();
+    species.color_r = 1.0;
+    species.color_g = 0.5;
+    species.color_b = 0.0;
+
+    let image = trail_map_to_rgba(&trail_map, &vec![species], &Palette::new(&RenderConfig::default()).unwrap(), &PostSettings::new(&PostConfig::default(), 0.0).unwrap(), 2, 2);
+
+    // Map row 0 is the bottom row of the image.
+    assert_eq!(&image[8..12], &[255, 128, 0, 255]);
+    assert_eq!(&image[0..4], &[0, 0, 0, 255]);
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let simulation_config = test_config(8, 8, 32);
+    let agents = construct_agents(&simulation_config).unwrap();
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(); MAX_SPECIES], agents, trail_map).unwrap();
+
+    for _ in 0..3 { simulation.step(1.0 / 60.0).unwrap(); }
+
+    let snapshot = simulation.snapshot().unwrap();
+    let mut bytes = Vec::new();
+    snapshot.write(&mut bytes).unwrap();
+    let loaded = Snapshot::read(&mut bytes.as_slice()).unwrap();
+
+    assert_eq!(loaded.frame, 3);
+    assert_eq!(loaded.config.agent_count, 32);
+    assert_eq!(bytemuck::cast_slice::<Species, u8>(&loaded.species), bytemuck::cast_slice::<Species, u8>(&snapshot.species));
+    assert_eq!(bytemuck::cast_slice::<Agent, u8>(&loaded.agents), bytemuck::cast_slice::<Agent, u8>(&snapshot.agents));
+    assert_eq!(bytemuck::cast_slice::<Trail, u8>(&loaded.maps[1]), bytemuck::cast_slice::<Trail, u8>(&snapshot.maps[1]));
+    assert_eq!(loaded.deposit, snapshot.deposit);
+
+    bytes[0] = b'X';
+    assert!(Snapshot::read(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn snapshot_rejects_tampered_buffers() {
+    let simulation_config = test_config(4, 4, 8);
+    let agents = construct_agents(&simulation_config).unwrap();
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let simulation = CpuSimulation::from_state(simulation_config, vec![test_species(); 2], agents, trail_map).unwrap();
+    let snapshot = simulation.snapshot().unwrap();
+
+    let read_back = |snapshot: &Snapshot| {
+        let mut bytes = Vec::new();
+        snapshot.write(&mut bytes).unwrap();
+        return Snapshot::read(&mut bytes.as_slice());
+    };
+    assert!(read_back(&snapshot).is_ok());
+
+    // More species than the GPU buffer holds.
+    let too_many = Snapshot { species: vec![test_species(); MAX_SPECIES + 1], ..snapshot.clone() };
+    assert!(read_back(&too_many).is_err());
+    assert!(read_back(&Snapshot { species: vec![], ..snapshot.clone() }).is_err());
+
+    // An agent of a species that isn't there.
+    let mut stray = snapshot.clone();
+    stray.agents[3].species = 2;
+    assert!(read_back(&stray).is_err());
+
+    // Cut short.
+    let mut bytes = Vec::new();
+    snapshot.write(&mut bytes).unwrap();
+    bytes.pop();
+    assert!(Snapshot::read(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn species_rebuild_from_their_buffers() {
+    let configs = vec![
+        SpeciesConfig { sensor_kernel: SensorKernel::Gaussian, color: Some(String::from("#ff8000")), ..test_species_config() },
+        SpeciesConfig { interactions: vec![-0.5, 1.0, 0.25], ..test_species_config() },
+        test_species_config(),
+    ];
+    let species = construct_species_from(&configs, &mut 0).unwrap();
+
+    for (index, original) in species.iter().enumerate() {
+        let rebuilt = Species::new(original.to_config(species.len()).unwrap(), index).unwrap();
+
+        assert_eq!(bytemuck::bytes_of(&rebuilt), bytemuck::bytes_of(original));
+    }
+}
+
+#[test]
+fn snapshot_resumes_run() {
+    let simulation_config = test_config(16, 16, 64);
+    let agents = construct_agents(&simulation_config).unwrap();
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(); MAX_SPECIES], agents, trail_map).unwrap();
+
+    for _ in 0..5 { simulation.step(1.0 / 60.0).unwrap(); }
+    let mut resumed = CpuSimulation::from_snapshot(simulation.snapshot().unwrap()).unwrap();
+
+    for _ in 0..5 {
+        simulation.step(1.0 / 60.0).unwrap();
+        resumed.step(1.0 / 60.0).unwrap();
+    }
+
+    assert_eq!(resumed.frame, 10);
+    assert_eq!(bytemuck::cast_slice::<Agent, u8>(&simulation.agents), bytemuck::cast_slice::<Agent, u8>(&resumed.agents));
+    assert_eq!(bytemuck::cast_slice::<Trail, u8>(&simulation.trail_map), bytemuck::cast_slice::<Trail, u8>(&resumed.trail_map));
+}
+
+#[test]
+fn config_root_precedence() {
+    let temp = std::env::temp_dir().join(format!("physarum_config_{}", std::process::id()));
+    let xdg = temp.join("xdg");
+    std::fs::create_dir_all(xdg.join("physarum")).unwrap();
+
+    let cli = Some(std::path::PathBuf::from("cli"));
+    let env = Some(std::ffi::OsString::from("env"));
+
+    assert_eq!(resolve_config_root(cli.clone(), env.clone(), Some(xdg.clone().into()), None), ConfigRoot::Directory("cli".into()));
+    assert_eq!(resolve_config_root(None, env.clone(), Some(xdg.clone().into()), None), ConfigRoot::Directory("env".into()));
+
+    // The XDG directory is only used once it holds a simulation config.
+    assert_eq!(resolve_config_root(None, None, Some(xdg.clone().into()), None), ConfigRoot::Bundled);
+    std::fs::write(xdg.join("physarum").join("simulation.ron"), "").unwrap();
+    assert_eq!(resolve_config_root(None, None, Some(xdg.clone().into()), None), ConfigRoot::Directory(xdg.join("physarum")));
+    assert_eq!(resolve_config_root(None, None, None, Some(temp.clone().into())), ConfigRoot::Bundled);
+
+    std::fs::remove_dir_all(&temp).unwrap();
+}
+
+#[test]
+fn config_roots_agree_on_species() {
+    let bundled = ConfigRoot::Bundled;
+    let directory = ConfigRoot::Directory(std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config"));
+
+    assert_eq!(bundled.species_names().unwrap(), directory.species_names().unwrap());
+    assert_eq!(bundled.read("simulation").unwrap(), directory.read("simulation").unwrap());
+
+    for name in bundled.species_names().unwrap() {
+        load_from::<SpeciesConfig>(&bundled, &format!("species/{}", name)).unwrap();
+    }
+    assert!(bundled.path("mask.png").is_err());
+}
+
+#[test]
+fn config_validation_reports_every_problem() {
+    let mut simulation_config = test_config(0, 8, 0);
+    simulation_config.decay_rate = 2.0;
+
+    let fields: Vec<Option<String>> = simulation_config.validate().into_iter().map(|error| error.field).collect();
+    assert_eq!(fields, vec![Some("agent_count".into()), Some("width".into()), Some("decay_rate".into())]);
+
+    assert!(test_config(8, 8, 1).validate().is_empty());
+
+    // Past what one dispatch of 32 wide workgroups reaches.
+    let fields: Vec<Option<String>> = test_config(4096, 4096, MAX_INVOCATIONS + 1).validate().into_iter().map(|error| error.field).collect();
+    assert_eq!(fields, vec![Some("agent_count".into()), Some("width".into())]);
+    assert!(test_config(1448, 1448, MAX_INVOCATIONS).validate().is_empty());
+
+    // Configs from before the map size had its own fields keep the old window size.
+    let sizeless = ConfigRoot::Bundled.read("simulation").unwrap().replace("width: 800,", "").replace("height: 800,", "");
+    let config = ron::from_str::<SimulationConfig>(&sizeless).unwrap();
+    assert_eq!((config.width, config.height), (800, 800));
+
+    // Configs from before `decay_rate` would otherwise never decay.
+    let old = ConfigRoot::Bundled.read("simulation").unwrap().replace("decay_rate: 0.1,", "");
+    assert!(ron::from_str::<SimulationConfig>(&old).is_err());
+}
+
+#[test]
+fn config_errors_have_file_context() {
+    let temp = std::env::temp_dir().join(format!("physarum_validate_{}", std::process::id()));
+    let bundled = ConfigRoot::Bundled;
+    std::fs::create_dir_all(temp.join("species")).unwrap();
+
+    std::fs::write(temp.join("simulation.ron"), bundled.read("simulation").unwrap().replace("agent_count: 2000000", "agent_count: 0")).unwrap();
+    std::fs::write(temp.join("window.ron"), bundled.read("window").unwrap()).unwrap();
+    std::fs::write(temp.join("species").join("0.ron"), bundled.read("species/0").unwrap().replace("move_speed: 0.5", "move_speed: -0.5")).unwrap();
+    std::fs::write(temp.join("species").join("1.ron"), "SpeciesConfig(").unwrap();
+
+    let errors = validate_all(&ConfigRoot::Directory(temp.clone()));
+    let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+
+    assert_eq!(errors.len(), 3);
+    assert!(messages[0].starts_with("simulation.ron:2:5: agent_count: "));
+    assert!(messages[1].starts_with("species/0.ron:") && messages[1].contains("move_speed: must be at least 0"));
+    assert!(errors[2].file == "species/1.ron" && errors[2].position.is_some());
+
+    std::fs::remove_dir_all(&temp).unwrap();
+}
+
+#[test]
+fn bundled_configs_are_valid() {
+    assert_eq!(validate_all(&ConfigRoot::Bundled), vec![]);
+}
+
+#[test]
+fn config_watcher_detects_edits() {
+    let temp = std::env::temp_dir().join(format!("physarum_watch_{}", std::process::id()));
+    std::fs::create_dir_all(temp.join("species")).unwrap();
+    std::fs::write(temp.join("simulation.ron"), "a").unwrap();
+
+    let mut watcher = ConfigWatcher::new(ConfigRoot::Directory(temp.clone()), std::time::Duration::ZERO).unwrap();
+    assert!(!watcher.poll());
+
+    std::fs::write(temp.join("simulation.ron"), "ab").unwrap();
+    assert!(watcher.poll());
+    assert!(!watcher.poll());
+
+    std::fs::write(temp.join("species").join("2.ron"), "a").unwrap();
+    assert!(watcher.poll());
+
+    std::fs::remove_dir_all(&temp).unwrap();
+}
+
+#[test]
+fn config_changes_that_need_a_rebuild() {
+    let simulation_config = test_config(16, 16, 10);
+
+    let mut tuned = simulation_config.clone();
+    tuned.decay_rate = 0.5;
+    tuned.diffusion_kernel = DiffusionKernel::Gaussian5;
+    tuned.seed = 3;
+    assert!(!simulation_config.requires_rebuild(&tuned));
+
+    assert!(simulation_config.requires_rebuild(&test_config(16, 16, 11)));
+    assert!(simulation_config.requires_rebuild(&test_config(16, 8, 10)));
+}
+
+#[test]
+fn species_interactions_expand_to_species_count() {
+    let mut config = load_from::<SpeciesConfig>(&ConfigRoot::Bundled, "species/0").unwrap();
+
+    config.interactions = vec![];
+    config.expand_interactions(1, 3);
+    assert_eq!(config.interactions, vec![0.0, 1.0, 0.0]);
+
+    config.interactions = vec![0.5, -1.0, 2.0, 1.0];
+    config.expand_interactions(0, 2);
+    assert_eq!(config.interactions, vec![0.5, -1.0]);
+}
+
+#[test]
+fn saved_configs_load_back() {
+    let temp = std::env::temp_dir().join(format!("physarum_save_{}", std::process::id()));
+    let bundled = ConfigRoot::Bundled;
+    std::fs::create_dir_all(temp.join("species")).unwrap();
+    for name in bundled.species_names().unwrap() {
+        std::fs::write(temp.join("species").join(format!("{}.ron", name)), bundled.read(&format!("species/{}", name)).unwrap()).unwrap();
+    }
+    let root = ConfigRoot::Directory(temp.clone());
+
+    let mut simulation_config = load_from::<SimulationConfig>(&bundled, "simulation").unwrap();
+    simulation_config.decay_rate = 0.25;
+    let window_config = load_from::<WindowConfig>(&bundled, "window").unwrap();
+    let mut species_configs: Vec<SpeciesConfig> = bundled.species_names().unwrap().iter().map(|name| load_from(&bundled, &format!("species/{}", name)).unwrap()).collect();
+    species_configs[1].move_speed = 1.5;
+
+    assert_eq!(save_all(&root, &simulation_config, &window_config, &RenderConfig::default(), &species_configs).unwrap(), temp);
+    assert_eq!(validate_all(&root), vec![]);
+    assert_eq!(load_from::<SimulationConfig>(&root, "simulation").unwrap().decay_rate, 0.25);
+    assert_eq!(load_from::<SpeciesConfig>(&root, "species/1").unwrap().move_speed, 1.5);
+
+    assert!(save_all(&root, &simulation_config, &window_config, &RenderConfig::default(), &species_configs[..1].to_vec()).is_err());
+
+    std::fs::remove_dir_all(&temp).unwrap();
+}
+
+#[test]
+fn brush_window_to_map() {
+    let simulation_config = test_config(8, 4, 0);
+
+    // The top left pixel is the last row of the map, the bottom right the first.
+    let top_left = window_to_map([0.5, 0.5], [800.0, 400.0]);
+    let bottom_right = window_to_map([799.5, 399.5], [800.0, 400.0]);
+
+    assert_eq!(get_cell_index(top_left, &simulation_config), Some(3 * 8));
+    assert_eq!(get_cell_index(bottom_right, &simulation_config), Some(7));
+    assert_eq!(window_to_map([400.0, 200.0], [800.0, 400.0]), [0.0, 0.0]);
+}
+
+#[test]
+fn cpu_brush_paints_and_erases_trail() {
+    let simulation_config = test_config(16, 16, 0);
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species()], vec![], trail_map).unwrap();
+
+    let center = get_cell_index([0.0, 0.0], &simulation.config).unwrap();
+    let far = get_cell_index([-0.9, -0.9], &simulation.config).unwrap();
+
+    simulation.apply_brush(&Brush::new(BrushTool::Paint, [0.0, 0.0], 0.4, 1.0, 1).unwrap()).unwrap();
+
+    assert!(simulation.trail_map[center].value[1] > 0.5);
+    assert_eq!(simulation.trail_map[center].value[0], 0.0);
+    assert_eq!(simulation.trail_map[far].total(), 0.0);
+
+    let painted = simulation.trail_map[center].value[1];
+    simulation.apply_brush(&Brush::new(BrushTool::Erase, [0.0, 0.0], 0.4, 0.5, 0).unwrap()).unwrap();
+
+    assert!(simulation.trail_map[center].value[1] < painted);
+    assert!(simulation.trail_map[center].value[1] > 0.0);
+}
+
+#[test]
+fn cpu_brush_spawns_and_pushes_agents() {
+    let simulation_config = test_config(16, 16, 10);
+    let agents = vec![Agent::default().unwrap(); 10];
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(), test_species()], agents, trail_map).unwrap();
+
+    // Wraps around the end of the agent buffer.
+    let spawn = Brush::new(BrushTool::Spawn, [0.5, 0.5], 0.1, 1.0, 1).unwrap().with_spawn(8, 4, 7).unwrap();
+    simulation.apply_brush(&spawn).unwrap();
+
+    let spawned: Vec<usize> = (0..10).filter(|index| simulation.agents[*index].species == 1).collect();
+    assert_eq!(spawned, vec![0, 1, 8, 9]);
+
+    for index in spawned {
+        let agent = &simulation.agents[index];
+        let offset = brush_offset(&spawn, [agent.position_x, agent.position_y], 1.0);
+
+        assert!((offset[0] * offset[0] + offset[1] * offset[1]).sqrt() <= 0.1 + 1e-6);
+    }
+
+    let before = simulation.agents[2];
+    simulation.apply_brush(&Brush::new(BrushTool::Push, [0.05, 0.0], 0.2, 1.0, 0).unwrap()).unwrap();
+
+    assert!(simulation.agents[2].position_x < before.position_x);
+    assert_eq!(simulation.agents[2].position_y, before.position_y);
+}
+
+#[test]
+fn camera_round_trips_positions() {
+    let mut camera = Camera::new().unwrap();
+    camera.zoom_at([0.5, -0.25], 4.0);
+    camera.pan([0.1, 0.2]);
+
+    let position = [0.3, -0.6];
+    let view = camera.map_to_view(position);
+    let back = camera.view_to_map(view);
+
+    assert!((back[0] - position[0]).abs() < 1e-5 && (back[1] - position[1]).abs() < 1e-5);
+    assert_eq!(camera.view_to_map([0.0, 0.0]), camera.offset);
+}
+
+#[test]
+fn camera_zooms_about_the_cursor() {
+    let mut camera = Camera::new().unwrap();
+    let cursor = [0.5, 0.5];
+    let before = camera.view_to_map(cursor);
+
+    camera.zoom_at(cursor, 2.0);
+
+    assert_eq!(camera.zoom, 2.0);
+    assert_eq!(camera.view_to_map(cursor), before);
+
+    camera.zoom_at(cursor, 1e6);
+    assert_eq!(camera.zoom, MAX_ZOOM);
+}
+
+#[test]
+fn camera_tiled_view_wraps() {
+    let mut camera = Camera::new().unwrap();
+    camera.toggle_tiled();
+
+    assert!(camera.is_tiled());
+    // The right hand tile is another copy of the map.
+    assert_eq!(camera.view_to_map([0.5, 0.0]), [-0.5, 0.0]);
+
+    camera.pan([-1.0, 0.0]);
+    assert!(camera.offset[0] >= -1.0 && camera.offset[0] < 1.0);
+}
+
+#[test]
+fn hex_colors() {
+    assert_eq!(parse_hex_color("#ff8000"), Ok([1.0, 128.0 / 255.0, 0.0]));
+    assert_eq!(parse_hex_color("0f0"), Ok([0.0, 1.0, 0.0]));
+    assert!(parse_hex_color("#ff80").is_err());
+    assert!(parse_hex_color("#gg0000").is_err());
+    assert_eq!(color_to_hex([1.0, 128.0 / 255.0, 0.0]), "#ff8000");
+
+    let mut config = test_species_config();
+    config.color = Some(String::from("#00ff00"));
+    assert_eq!(Species::new(config.clone(), 0).unwrap().color_g, 1.0);
+
+    config.color = Some(String::from("green"));
+    assert_eq!(config.validate()[0].field.as_deref(), Some("color"));
+    assert!(Species::new(config, 0).is_err());
+
+    // Neither form given.
+    let uncoloured = SpeciesConfig { color_r: 0.0, color_g: 0.0, color_b: 0.0, color: None, ..test_species_config() };
+    assert_eq!(uncoloured.validate()[0].field.as_deref(), Some("color"));
+    assert!(SpeciesConfig { color: Some(String::from("#000000")), ..uncoloured }.validate().is_empty());
+}
+
+#[test]
+fn palette_blends_species() {
+    let mut red = test_species();
+    (red.color_r, red.color_g, red.color_b) = (1.0, 0.0, 0.0);
+    let mut blue = test_species();
+    (blue.color_r, blue.color_g, blue.color_b) = (0.0, 0.0, 1.0);
+    let species = vec![red, blue, red];
+    let value = [0.25, 0.5, 0.5, 0.0];
+
+    let mut render_config = RenderConfig::default();
+    assert_eq!(shade(value, &species, &Palette::new(&render_config).unwrap()), [0.75, 0.0, 0.5]);
+
+    render_config.blend = SpeciesBlend::Max;
+    assert_eq!(shade(value, &species, &Palette::new(&render_config).unwrap()), [0.5, 0.0, 0.5]);
+
+    // Ties go to the lower species index.
+    render_config.blend = SpeciesBlend::Dominant;
+    assert_eq!(shade(value, &species, &Palette::new(&render_config).unwrap()), [0.0, 0.0, 0.5]);
+
+    render_config.exposure = 2.0;
+    render_config.gamma = 2.0;
+    assert_eq!(shade(value, &species, &Palette::new(&render_config).unwrap()), [0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn palette_samples_gradients() {
+    let mut render_config = RenderConfig::default();
+    render_config.color_map = ColorMap::Gradient(vec![(0.0, String::from("#000000")), (0.5, String::from("#ff0000")), (1.0, String::from("#ffffff"))]);
+    let palette = Palette::new(&render_config).unwrap();
+    let species = vec![test_species(), test_species()];
+
+    assert_eq!(shade([0.0; 4], &species, &palette), [0.0, 0.0, 0.0]);
+    assert_eq!(shade([0.25, 0.0, 0.0, 0.0], &species, &palette), [0.5, 0.0, 0.0]);
+    // The total of every species' trail is mapped, clamped to the last stop.
+    assert_eq!(shade([0.5, 0.25, 0.0, 0.0], &species, &palette), [1.0, 0.5, 0.5]);
+    assert_eq!(shade([4.0, 0.0, 0.0, 0.0], &species, &palette), [1.0, 1.0, 1.0]);
+
+    render_config.color_map = ColorMap::Viridis;
+    let palette = Palette::new(&render_config).unwrap();
+    assert_eq!(palette.stop_count, 9);
+    assert_eq!(color_to_hex(shade([1.0, 0.0, 0.0, 0.0], &species, &palette)), "#fde725");
+
+    render_config.color_map = ColorMap::Gradient(vec![(0.5, String::from("#000000")), (0.25, String::from("#ffffff"))]);
+    assert_eq!(render_config.validate().len(), 1);
+    assert!(Palette::new(&render_config).is_err());
+}
+
+#[test]
+fn camera_keeps_map_aspect() {
+    let mut camera = Camera::new().unwrap();
+
+    camera.fit([1600.0, 800.0], [400.0, 400.0], false);
+    assert_eq!(camera.viewport, [1.0, 1.0]);
+
+    // A square map in a wide window is letterboxed left and right.
+    camera.fit([1600.0, 800.0], [400.0, 400.0], true);
+    assert_eq!(camera.viewport, [0.5, 1.0]);
+    assert_eq!(camera.view_to_map([0.5, 1.0]), [1.0, 1.0]);
+    assert_eq!(camera.map_to_view([-1.0, 0.0]), [-0.5, 0.0]);
+
+    camera.fit([800.0, 800.0], [800.0, 200.0], true);
+    assert_eq!(camera.viewport, [1.0, 0.25]);
+}
+
+#[test]
+fn agent_style_subsamples_and_colours() {
+    let mut render_config = RenderConfig::default();
+    render_config.agent_subsample = 3;
+    let agent_style = AgentStyle::new(&render_config, [800.0, 400.0]).unwrap();
+
+    assert_eq!(agent_style.pixel_size, [2.0 / 800.0, 2.0 / 400.0]);
+    assert_eq!(agent_style.drawn_agents(9), 3);
+    assert_eq!(agent_style.drawn_agents(10), 4);
+    assert_eq!(AgentStyle::new(&RenderConfig::default(), [1.0, 1.0]).unwrap().drawn_agents(10), 10);
+
+    let mut species = test_species();
+    (species.color_r, species.color_g, species.color_b) = (0.25, 0.5, 1.0);
+    let agent = Agent::default().unwrap().with_species(0).unwrap();
+    assert_eq!(agent_color(&agent, &vec![species], &agent_style), [0.25, 0.5, 1.0]);
+
+    render_config.agent_coloring = AgentColoring::Heading;
+    let agent_style = AgentStyle::new(&render_config, [800.0, 400.0]).unwrap();
+    assert_eq!(agent_color(&agent, &vec![species], &agent_style), [1.0, 0.0, 0.0]);
+
+    let third = std::f32::consts::TAU / 3.0;
+    let green = heading_color(third);
+    let blue = heading_color(-third);
+    assert!((green[0]).abs() < 1e-5 && (green[1] - 1.0).abs() < 1e-5 && green[2].abs() < 1e-5);
+    assert!(blue[0].abs() < 1e-5 && blue[1].abs() < 1e-5 && (blue[2] - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn post_defaults_leave_frames_unchanged() {
+    let post_settings = PostSettings::new(&PostConfig::default(), 0.0).unwrap();
+    let mut image = vec![[0.0, 0.25, 0.5], [1.0, 0.75, 2.0]];
+
+    post_process(&mut image, 2, 1, &post_settings);
+
+    assert_eq!(image, vec![[0.0, 0.25, 0.5], [1.0, 0.75, 1.0]]);
+}
+
+#[test]
+fn post_tone_maps_compress_highlights() {
+    let mut post_config = PostConfig::default();
+
+    for curve in [ToneMap::Reinhard, ToneMap::Aces, ToneMap::Filmic] {
+        post_config.tone_map = curve;
+        let post_settings = PostSettings::new(&post_config, 0.0).unwrap();
+        let values: Vec<f32> = [0.0, 0.1, 1.0, 4.0, 64.0].iter().map(|v| tone_map([*v; 3], &post_settings)[0]).collect();
+
+        assert!(values[0].abs() < 1e-3, "{:?} {:?}", curve, values);
+        assert!(values.windows(2).all(|pair| pair[1] > pair[0]), "{:?} {:?}", curve, values);
+        assert!(values[4] <= 1.0 && values[4] > 0.8, "{:?} {:?}", curve, values);
+    }
+}
+
+#[test]
+fn post_auto_exposure_uses_lit_pixels() {
+    let mut post_config = PostConfig::default();
+    post_config.exposure.auto = true;
+    post_config.exposure.adaptation_rate = 2.0;
+    let post_settings = PostSettings::new(&post_config, 0.5).unwrap();
+
+    // The background is left out, so half the pixels being black changes nothing.
+    let mut bins = [0; HISTOGRAM_BINS];
+    for color in [[0.0; 3], [0.0; 3], [4.0; 3], [4.0; 3]] {
+        if let Some(bin) = histogram_bin(color, &post_settings) { bins[bin] += 1; }
+    }
+    let luminance = histogram_luminance(&bins, &post_settings).unwrap();
+    let bin_width = post_settings.log_luminance_range / HISTOGRAM_BINS as f32;
+
+    assert_eq!(bins.iter().sum::<u32>(), 2);
+    assert!((luminance.log2() - 2.0).abs() <= bin_width);
+    assert!((post_settings.exposure(luminance) * luminance - 0.18).abs() < 1e-5);
+    assert_eq!(histogram_luminance(&[0; HISTOGRAM_BINS], &post_settings), None);
+
+    // Adapts part of the way over one frame, all of it on the first.
+    let adapted = post_settings.adapt(1.0, 2.0);
+    assert!(adapted > 1.0 && adapted < 2.0);
+    assert_eq!(post_settings.adapt(0.0, 2.0), 2.0);
+}
+
+#[test]
+fn post_bloom_spreads_bright_pixels() {
+    let mut post_config = PostConfig::default();
+    post_config.bloom.enabled = true;
+    post_config.bloom.radius = 2;
+    let post_settings = PostSettings::new(&post_config, 0.0).unwrap();
+
+    let weights = bloom_weights(4);
+    assert!((weights[0] + 2.0 * weights[1..].iter().sum::<f32>() - 1.0).abs() < 1e-5);
+
+    let mut image = vec![[0.0; 3]; 16 * 16];
+    image[8 * 16 + 8] = [0.5; 3];
+    post_process(&mut image, 16, 16, &post_settings);
+    assert!(image[8 * 16 + 11][0] == 0.0, "below the threshold nothing glows");
+
+    image = vec![[0.0; 3]; 16 * 16];
+    image[8 * 16 + 8] = [16.0; 3];
+    post_process(&mut image, 16, 16, &post_settings);
+    assert!(image[8 * 16 + 11][0] > 0.0 && image[8 * 16 + 11][0] < image[8 * 16 + 9][0]);
+}
+
+#[test]
+fn post_errors_point_at_nested_fields() {
+    let contents = ConfigRoot::Bundled.read("render").unwrap();
+    let error = ConfigError::field("post.vignette.radius", "bad").in_file("render.ron", &contents);
+    let (line, _) = error.position.unwrap();
+
+    assert!(contents.lines().nth(line - 1).unwrap().trim() == "radius: 0.5,");
+    assert!(contents.lines().take(line).any(|line| line.trim() == "vignette: ("));
+
+    let mut render_config = RenderConfig::default();
+    render_config.post.bloom.radius = MAX_BLOOM_RADIUS + 1;
+    render_config.post.exposure.max_log_luminance = -20.0;
+    let fields: Vec<Option<String>> = render_config.validate().into_iter().map(|error| error.field).collect();
+    assert_eq!(fields, vec![Some("post.exposure.max_log_luminance".into()), Some("post.bloom.radius".into())]);
+}
+
+#[test]
+fn record_options_from_args() {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+
+    assert_eq!(RecordOptions::from_args(&args(&["physarum"])).unwrap(), None);
+
+    let options = RecordOptions::from_args(&args(&["physarum", "--record", "-", "--record-every", "4", "--record-size", "320x180"])).unwrap().unwrap();
+    assert_eq!(options, RecordOptions { format: RecordFormat::Raw, output: "-".into(), every: 4, frames: None, size: Some([320, 180]), fps: 30 });
+
+    assert_eq!(RecordOptions::from_args(&args(&["physarum", "--record", "run.png"])).unwrap().unwrap().format, RecordFormat::Apng);
+    assert_eq!(RecordOptions::from_args(&args(&["physarum", "--record", "frames"])).unwrap().unwrap().format, RecordFormat::PngSequence);
+    assert!(RecordOptions::from_args(&args(&["physarum", "--record", "frames", "--record-size", "320"])).is_err());
+    assert!(RecordOptions::from_args(&args(&["physarum", "--record", "frames", "--record-every", "0"])).is_err());
+
+    // Headless accepts the recording flags alongside its own.
+    let headless = HeadlessOptions::from_args(&args(&["physarum", "--headless", "--record", "frames", "--record-frames", "3"])).unwrap().unwrap();
+    assert_eq!(headless.record.unwrap().frames, Some(3));
+}
+
+#[test]
+fn headless_records_every_nth_step() {
+    let simulation_config = test_config(8, 4, 16);
+    let agents = construct_agents(&simulation_config).unwrap();
+    let trail_map = construct_trail_map(&simulation_config).unwrap();
+    let mut simulation = CpuSimulation::from_state(simulation_config, vec![test_species(); MAX_SPECIES], agents, trail_map).unwrap();
+
+    let output = std::env::temp_dir().join(format!("physarum_record_{}", std::process::id()));
+    let sequence = RecordOptions { format: RecordFormat::PngSequence, output: output.join("sequence"), every: 3, frames: Some(3), size: Some([4, 2]), fps: 30 };
+    let options = HeadlessOptions { steps: 12, interval: 12, output: output.clone(), record: Some(sequence) };
+    run_batch(&mut simulation, &Palette::new(&RenderConfig::default()).unwrap(), &PostSettings::new(&PostConfig::default(), 0.0).unwrap(), &options).unwrap();
+
+    // Steps 1, 4 and 7, then the frame limit stops it.
+    for frame in 0..3 {
+        let decoder = png::Decoder::new(std::fs::File::open(output.join("sequence").join(format!("frame_{:06}.png", frame))).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (4, 2));
+    }
+    assert!(!output.join("sequence").join("frame_000003.png").exists());
+
+    let mut recorder = Recorder::new(RecordOptions { format: RecordFormat::Apng, output: output.join("run.png"), every: 1, frames: None, size: None, fps: 10 }).unwrap();
+    for value in [0, 128] {
+        assert!(!recorder.skip());
+        recorder.push_frame(&vec![value; 2 * 2 * 4], 2, 2).unwrap();
+    }
+    recorder.finish().unwrap();
+
+    let decoder = png::Decoder::new(std::fs::File::open(output.join("run.png")).unwrap());
+    let reader = decoder.read_info().unwrap();
+    assert_eq!(reader.info().animation_control.unwrap().num_frames, 2);
+
+    std::fs::remove_dir_all(&output).unwrap();
+}
+
+#[test]
+fn recorded_frames_resize() {
+    let rgba: Vec<u8> = (0..4).flat_map(|i| [i, i, i, 255]).collect();
+
+    assert_eq!(resize_rgba(&rgba, [2, 2], [1, 1]), vec![0, 0, 0, 255]);
+    assert_eq!(resize_rgba(&rgba, [2, 2], [4, 1]), vec![0, 0, 0, 255, 0, 0, 0, 255, 1, 1, 1, 255, 1, 1, 1, 255]);
+}
+
+#[test]
+fn screenshots_carry_their_configs() {
+    let output = std::env::temp_dir().join(format!("physarum_screenshot_{}", std::process::id()));
+    let path = output.join("shot.png");
+    let simulation_config = SimulationConfig { seed: 1234, decay_rate: 0.125, ..test_config(8, 4, 16) };
+    let species_configs = vec![test_species_config(), SpeciesConfig { move_speed: 2.5, ..test_species_config() }];
+    let render_config = RenderConfig { gamma: 1.8, ..RenderConfig::default() };
+
+    let provenance = Provenance::new(&simulation_config, &species_configs, &render_config, 42).unwrap();
+    save_screenshot(&path, &vec![255; 2 * 2 * 4], [2, 2], &provenance).unwrap();
+
+    let loaded = load_provenance(&path).unwrap();
+    assert_eq!((loaded.seed, loaded.frame), (1234, 42));
+    assert_eq!(loaded.simulation_config.seed, 1234);
+    assert_eq!(loaded.simulation_config.decay_rate, 0.125);
+    assert_eq!(loaded.species_configs.len(), 2);
+    assert_eq!(loaded.species_configs[1].move_speed, 2.5);
+    assert_eq!(loaded.render_config.gamma, 1.8);
+
+    // Any PNG reader sees the frame itself.
+    let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+    let reader = decoder.read_info().unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (2, 2));
+
+    let mut chunks = provenance.to_text_chunks().unwrap();
+    chunks.retain(|(keyword, _)| keyword != SIMULATION_KEYWORD);
+    assert!(Provenance::from_text_chunks(&chunks).is_err());
+
+    let mut chunks = provenance.to_text_chunks().unwrap();
+    chunks.retain(|(keyword, _)| keyword != RENDER_KEYWORD);
+    assert!(Provenance::from_text_chunks(&chunks).is_err());
+
+    std::fs::remove_dir_all(&output).unwrap();
+}
+
+#[test]
+fn screenshots_restore_a_config_dir() {
+    let output = std::env::temp_dir().join(format!("physarum_restore_{}", std::process::id()));
+    let path = output.join("physarum_42.png");
+    let bundled = ConfigRoot::Bundled;
+    let simulation_config = SimulationConfig { seed: 99, ..load_from::<SimulationConfig>(&bundled, "simulation").unwrap() };
+    let species_configs: Vec<SpeciesConfig> = bundled.species_names().unwrap().iter().map(|name| load_from(&bundled, &format!("species/{}", name)).unwrap()).collect();
+
+    let provenance = Provenance::new(&simulation_config, &species_configs, &RenderConfig::default(), 42).unwrap();
+    save_screenshot(&path, &vec![0; 4], [1, 1], &provenance).unwrap();
+
+    let args: Vec<String> = ["physarum", "restore", path.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+    let (screenshot, dir) = restore_args(&args).unwrap().unwrap();
+    assert_eq!(dir, std::path::Path::new("restored").join("physarum_42"));
+
+    let dir = output.join("restored");
+    restore_screenshot(&bundled, &screenshot, &dir).unwrap();
+
+    let root = ConfigRoot::Directory(dir.clone());
+    assert_eq!(validate_all(&root), vec![]);
+    assert_eq!(load_from::<SimulationConfig>(&root, "simulation").unwrap().seed, 99);
+    assert_eq!(root.species_names().unwrap(), vec![String::from("0"), String::from("1")]);
+
+    // An existing config is never written over.
+    assert!(restore_screenshot(&bundled, &screenshot, &dir).is_err());
+
+    std::fs::remove_dir_all(&output).unwrap();
+}
+
+#[test]
+fn steps_carry_fractions_and_cap() {
+    // Half speed runs a step every other frame.
+    assert_eq!(steps_for(0.0, 1, 0.5), (0, 0.5));
+    assert_eq!(steps_for(0.5, 1, 0.5), (1, 0.0));
+    assert_eq!(steps_for(0.25, 3, 1.5), (4, 0.75));
+
+    // Paused or negative speed never steps and keeps the carried fraction.
+    assert_eq!(steps_for(0.5, 4, 0.0), (0, 0.5));
+    assert_eq!(steps_for(0.5, 4, -2.0), (0, 0.5));
+
+    // Past the cap the backlog is dropped.
+    assert_eq!(steps_for(0.5, 16, 4.0), (MAX_STEPS_PER_FRAME, 0.5));
+    assert_eq!(steps_for(0.5, 16, 8.0), (MAX_STEPS_PER_FRAME, 0.0));
+}
+
+#[test]
+fn cpu_crowded_cells_saturate() {
+    let simulation_config = test_config(1, 1, 0);
+    let species = Species::new(SpeciesConfig { move_speed: 0.0, weight: 1.0, ..test_species_config() }, 0).unwrap();
+
+    // 65,536 agents at weight 1 fill the fixed point deposit of a cell.
+    let merged = |agent_count: usize| {
+        let agents = vec![Agent::default().unwrap(); agent_count];
+        let trail_map = construct_trail_map(&simulation_config).unwrap();
+        let mut simulation = CpuSimulation::from_state(simulation_config.clone(), vec![species], agents, trail_map).unwrap();
+
+        simulation.step(0.01).unwrap();
+        simulation.step(0.01).unwrap();
+
+        return simulation.trail_map[0].value[0];
+    };
+
+    assert!(merged(70_000) >= merged(60_000));
+    assert!(merged(140_000) >= merged(70_000));
+}
@@ -1,18 +1,24 @@
 use ggez::{GameResult};
 
 #[repr(C)]
-#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug)]
 pub struct Trail {
     pub position: [f32; 2],
-    pub value: f32,
-    pub t: f32,
+    pub species: u32,
+    pub _padding: u32,
+    // One channel per species, see `MAX_SPECIES`.
+    pub value: [f32; 4],
 }
 
 impl Trail {
-    pub fn new(position: [f32; 2], value: f32) -> GameResult<Trail> {
-        let trail = Trail { position, value, t: 0.0 };
+    pub fn new(position: [f32; 2], value: [f32; 4]) -> GameResult<Trail> {
+        let trail = Trail { position, species: 0, _padding: 0, value };
 
         return Ok(trail);
     }
+
+    pub fn total(&self) -> f32 {
+        return self.value.iter().sum();
+    }
 }
 
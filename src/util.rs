@@ -1,6 +1,6 @@
 use std::num::NonZeroU32;
 use std::{borrow::Cow};
-use ggez::{GameResult};
+use ggez::{GameResult, GameError};
 use wgpu::util::DeviceExt;
 
 
@@ -96,3 +96,28 @@ pub fn construct_bind_group(device: &wgpu::Device, label: &str, layout: &wgpu::B
 
     return Ok(bind_group);
 }
+
+// Copies `size` bytes of `buffer` into a mappable buffer and blocks until they
+// can be read. `buffer` needs `COPY_SRC`.
+pub fn read_buffer<T: bytemuck::Pod>(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer, size: usize) -> GameResult<Vec<T>> {
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: size as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Readback Encoder") });
+    command_encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size as u64);
+    queue.submit(Some(command_encoder.finish()));
+
+    let slice = staging_buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping).map_err(|e| GameError::RenderError(format!("Failed to read back buffer: {:?}", e)))?;
+
+    let data = slice.get_mapped_range().chunks_exact(std::mem::size_of::<T>()).map(bytemuck::pod_read_unaligned).collect();
+    staging_buffer.unmap();
+
+    return Ok(data);
+}
@@ -0,0 +1,68 @@
+use std::{fs, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+use ggez::{GameResult};
+use crate::{ConfigRoot};
+
+
+// Polls the config directory for changes by comparing modification times and
+// sizes. Bundled configs never change, so they are not watched.
+pub struct ConfigWatcher {
+    pub root: ConfigRoot,
+    pub interval: Duration,
+
+    stamps: Vec<(PathBuf, SystemTime, u64)>,
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(root: ConfigRoot, interval: Duration) -> GameResult<ConfigWatcher> {
+        let stamps = scan(&root);
+        let last_poll = Instant::now();
+
+        let watcher = ConfigWatcher { root, interval, stamps, last_poll };
+
+        return Ok(watcher);
+    }
+
+    // True once per change to any `.ron` file or to the set of species files.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < self.interval { return false; }
+        self.last_poll = Instant::now();
+
+        let stamps = scan(&self.root);
+        if stamps == self.stamps { return false; }
+
+        self.stamps = stamps;
+
+        return true;
+    }
+}
+
+fn scan(root: &ConfigRoot) -> Vec<(PathBuf, SystemTime, u64)> {
+    let mut stamps = Vec::new();
+
+    if let ConfigRoot::Directory(dir) = root {
+        scan_dir(dir, &mut stamps);
+        scan_dir(&dir.join("species"), &mut stamps);
+    }
+
+    stamps.sort_by(|a, b| a.0.cmp(&b.0));
+
+    return stamps;
+}
+
+fn scan_dir(dir: &Path, stamps: &mut Vec<(PathBuf, SystemTime, u64)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.extension().is_some_and(|ext| ext == "ron") { continue; }
+
+        if let Ok(metadata) = entry.metadata() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            stamps.push((path, modified, metadata.len()));
+        }
+    }
+}